@@ -0,0 +1,422 @@
+use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
+
+use serde::{Deserialize, Serialize};
+
+use std::env::{home_dir, var_os};
+use std::path::PathBuf;
+
+const SOCKET_ENV_VAR: &str = "CMAN_AGENT_SOCK";
+pub const DEFAULT_TIMEOUT: u64 = 900;
+
+/// A request sent to the agent. Each connection carries one request and its response as single
+/// lines of json.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "request", rename_all = "lowercase")]
+enum Request {
+    /// Ask for the raw key of the database at db.
+    Get { db: String },
+
+    /// Remember the raw key of the database at db.
+    Put { db: String, key: SecretString },
+
+    /// Forget every key and stop the agent.
+    Lock,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Response {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<SecretString>,
+}
+
+/// The socket the agent listens on: $CMAN_AGENT_SOCK, otherwise cman/agent.sock in
+/// $XDG_RUNTIME_DIR or in $HOME/.cache.
+pub fn socket_path() -> Result<PathBuf, CMError> {
+    if let Some(path) = non_empty_env(SOCKET_ENV_VAR) {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = match non_empty_env("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match home_dir() {
+            Some(home) => home.join(".cache"),
+            None => return Err(CustomError::new("Could not get the agent socket path").into()),
+        },
+    };
+    Ok(dir.join("cman").join("agent.sock"))
+}
+
+/// The raw key the agent holds for a database, if an agent is running and has one. It is the value
+/// of PRAGMA key derived from the master password, which the password cannot be recovered from.
+pub fn cached_key(dbfile: &str) -> Option<SecretString> {
+    let db = db_id(dbfile);
+    send_request(&Request::Get { db }).ok()?.key
+}
+
+/// Hands the raw key that has just unlocked a database to the agent, if one is running.
+pub fn cache_key(dbfile: &str, key: &SecretString) {
+    let db = db_id(dbfile);
+    let _ = send_request(&Request::Put {
+        db,
+        key: key.clone(),
+    });
+}
+
+/// Asks a running agent to forget its keys and stop. Returns false if no agent was running.
+pub fn lock() -> Result<bool, CMError> {
+    match send_request(&Request::Lock) {
+        Ok(_) => Ok(true),
+        Err(CMError::IOError(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether an agent is listening on the socket.
+pub fn is_running() -> bool {
+    platform::connect().is_ok()
+}
+
+/// Databases are told apart by their canonical path so that the same file opened through a
+/// vault name, $CMAN_DBFILE or a relative path shares one key.
+fn db_id(dbfile: &str) -> String {
+    std::fs::canonicalize(dbfile)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or(dbfile.to_string())
+}
+
+fn send_request(request: &Request) -> Result<Response, CMError> {
+    let stream = platform::connect()?;
+    platform::exchange(stream, request)
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    var_os(name)
+        .and_then(|v| v.into_string().ok())
+        .filter(|v| !v.is_empty())
+}
+
+fn protocol_error() -> CMError {
+    CustomError::new("The agent sent an invalid response").into()
+}
+
+pub use platform::serve;
+
+#[cfg(unix)]
+mod platform {
+    use super::{Request, Response, SOCKET_ENV_VAR, protocol_error, socket_path};
+    use crate::util::errors::{CMError, CustomError};
+    use crate::util::secret::SecretString;
+
+    use serde::Serialize;
+    use zeroize::Zeroizing;
+
+    use std::collections::HashMap;
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+    /// Requests and responses hold a database path and a key, far less than this.
+    const MAX_LINE: usize = 16 * 1024;
+
+    pub fn connect() -> Result<UnixStream, CMError> {
+        connect_to(&socket_path()?)
+    }
+
+    fn connect_to(path: &Path) -> Result<UnixStream, CMError> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    pub fn exchange(mut stream: UnixStream, request: &Request) -> Result<Response, CMError> {
+        write_line(&mut stream, request)?;
+        let response = read_line(&mut stream)?;
+        serde_json::from_slice(&response).map_err(|_| protocol_error())
+    }
+
+    /// Runs the agent until it has been idle for `timeout` seconds or is locked. Like ssh-agent it
+    /// keeps the keys of unlocked vaults in memory and hands them out over a socket that only the
+    /// user can reach, so commands run shortly after each other ask for the password once.
+    pub fn serve(timeout: u64) -> Result<(), CMError> {
+        serve_at(&socket_path()?, timeout)
+    }
+
+    fn serve_at(path: &Path, timeout: u64) -> Result<(), CMError> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        private_dir(dir)?;
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(CustomError::new(&format!(
+                    "An agent is already running at {}",
+                    path.display()
+                ))
+                .into());
+            }
+            // a socket left behind by an agent that did not shut down cleanly.
+            std::fs::remove_file(path)?;
+        }
+
+        // the socket gets the umask permissions until they are set below, which is harmless because
+        // nobody else can get into the directory.
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+
+        let timeout = Duration::from_secs(timeout);
+        let mut keys: HashMap<String, SecretString> = HashMap::new();
+        let mut last_used = Instant::now();
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    last_used = Instant::now();
+                    if let Ok(false) = handle_client(stream, &mut keys) {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if last_used.elapsed() >= timeout {
+                        break;
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(path);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        keys.clear();
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Creates dir as 0700 if it is missing and makes sure that only its owner can get into it.
+    /// Binding in a 0700 directory of another user fails, so a socket bound in it can only be
+    /// reached by us.
+    fn private_dir(dir: &Path) -> Result<(), CMError> {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        if std::fs::metadata(dir)?.permissions().mode() & 0o077 != 0 {
+            return Err(CustomError::new(&format!(
+                "The agent socket directory {} can be accessed by other users, run chmod 700 on it or set {} to a path in a private directory",
+                dir.display(),
+                SOCKET_ENV_VAR
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Answers one request. Returns false when the agent should stop.
+    fn handle_client(
+        mut stream: UnixStream,
+        keys: &mut HashMap<String, SecretString>,
+    ) -> Result<bool, CMError> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let line = read_line(&mut stream)?;
+        let request: Request = match serde_json::from_slice(&line) {
+            Ok(r) => r,
+            Err(_) => {
+                write_line(&mut stream, &Response::default())?;
+                return Ok(true);
+            }
+        };
+
+        let (response, keep_running) = match request {
+            Request::Get { db } => {
+                let key = keys.get(&db).cloned();
+                (
+                    Response {
+                        ok: key.is_some(),
+                        key,
+                    },
+                    true,
+                )
+            }
+            Request::Put { db, key } => {
+                keys.insert(db, key);
+                (
+                    Response {
+                        ok: true,
+                        key: None,
+                    },
+                    true,
+                )
+            }
+            Request::Lock => {
+                keys.clear();
+                (
+                    Response {
+                        ok: true,
+                        key: None,
+                    },
+                    false,
+                )
+            }
+        };
+        write_line(&mut stream, &response)?;
+        Ok(keep_running)
+    }
+
+    /// Writes a message as one line of json. It is serialized straight into the socket, so no
+    /// buffer is left holding a key.
+    fn write_line<T: Serialize>(stream: &mut UnixStream, message: &T) -> Result<(), CMError> {
+        serde_json::to_writer(&mut *stream, message).map_err(|_| protocol_error())?;
+        stream.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Reads one line into a buffer that is wiped when dropped. It is read a byte at a time into
+    /// room allocated up front, so neither a reader's buffer nor a reallocation keeps a copy.
+    fn read_line(stream: &mut UnixStream) -> Result<Zeroizing<Vec<u8>>, CMError> {
+        let mut line = Zeroizing::new(Vec::with_capacity(MAX_LINE));
+        let mut byte = Zeroizing::new([0u8; 1]);
+        while line.len() < MAX_LINE && stream.read(byte.as_mut())? == 1 && byte[0] != b'\n' {
+            line.push(byte[0]);
+        }
+        Ok(line)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use std::path::PathBuf;
+        use std::thread::JoinHandle;
+
+        use tempfile::TempDir;
+
+        /// Starts an agent on a socket in a directory that does not exist yet, and waits until it
+        /// listens.
+        fn start_agent(dir: &TempDir) -> (PathBuf, JoinHandle<Result<(), CMError>>) {
+            let path = dir.path().join("agent").join("agent.sock");
+            let socket = path.clone();
+            let handle = std::thread::spawn(move || serve_at(&socket, 60));
+            for _ in 0..100 {
+                if connect_to(&path).is_ok() {
+                    return (path, handle);
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            panic!("the agent did not start");
+        }
+
+        fn send(path: &Path, request: &Request) -> Response {
+            exchange(connect_to(path).unwrap(), request).unwrap()
+        }
+
+        fn get(path: &Path, db: &str) -> Response {
+            send(path, &Request::Get { db: db.to_string() })
+        }
+
+        fn lock(path: &Path, handle: JoinHandle<Result<(), CMError>>) {
+            assert!(send(path, &Request::Lock).ok);
+            handle.join().unwrap().unwrap();
+        }
+
+        #[test]
+        fn put_then_get_returns_the_key() {
+            let dir = TempDir::new().unwrap();
+            let (path, handle) = start_agent(&dir);
+
+            let key = SecretString::from("x'00112233'");
+            let put = Request::Put {
+                db: "/vaults/a.db".to_string(),
+                key: key.clone(),
+            };
+            assert!(send(&path, &put).ok);
+            let response = get(&path, "/vaults/a.db");
+            assert!(response.ok);
+            assert_eq!(response.key, Some(key));
+
+            lock(&path, handle);
+        }
+
+        #[test]
+        fn unknown_database_has_no_key() {
+            let dir = TempDir::new().unwrap();
+            let (path, handle) = start_agent(&dir);
+
+            let response = get(&path, "/vaults/unknown.db");
+            assert!(!response.ok);
+            assert_eq!(response.key, None);
+
+            lock(&path, handle);
+        }
+
+        #[test]
+        fn lock_forgets_keys_and_stops() {
+            let dir = TempDir::new().unwrap();
+            let (path, handle) = start_agent(&dir);
+            let put = Request::Put {
+                db: "/vaults/a.db".to_string(),
+                key: "x'00'".into(),
+            };
+            assert!(send(&path, &put).ok);
+
+            lock(&path, handle);
+            assert!(!path.exists());
+            assert!(connect_to(&path).is_err());
+
+            // a new agent on the same socket starts without the key.
+            let (path, handle) = start_agent(&dir);
+            assert!(!get(&path, "/vaults/a.db").ok);
+            lock(&path, handle);
+        }
+
+        #[test]
+        fn socket_is_private() {
+            let dir = TempDir::new().unwrap();
+            let (path, handle) = start_agent(&dir);
+
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o600);
+            assert_eq!(mode(path.parent().unwrap()), 0o700);
+            lock(&path, handle);
+
+            // a directory other users can get into is refused.
+            let shared = dir.path().join("shared");
+            std::fs::create_dir(&shared).unwrap();
+            std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(serve_at(&shared.join("agent.sock"), 60).is_err());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use super::{Request, Response};
+    use crate::util::errors::{CMError, CustomError};
+
+    pub struct Stream;
+
+    fn unsupported() -> CMError {
+        CustomError::new("The agent is only supported on unix systems").into()
+    }
+
+    pub fn connect() -> Result<Stream, CMError> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+    }
+
+    pub fn exchange(_stream: Stream, _request: &Request) -> Result<Response, CMError> {
+        Err(unsupported())
+    }
+
+    pub fn serve(_timeout: u64) -> Result<(), CMError> {
+        Err(unsupported())
+    }
+}
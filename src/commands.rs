@@ -1,15 +1,23 @@
-use crate::db;
-use crate::objects::{APIObj, AccountObj, Secret};
+use crate::db::cipher::CipherParams;
+use crate::db::key::{self, PasswordOption};
+use crate::db::{self, SecretLookup};
+use crate::objects::{APIObj, AccountObj, CustomField, NoteObj, Secret, SshObj};
 use crate::util::argparser::{
-    AddArgs, ChangeArgs, CmanArgs, Commands, DeleteArgs, FieldType, GetArgs, InitArgs, LsArgs,
-    PullArgs, SecretType,
+    AddArgs, AttachArgs, AttachmentsArgs, AuditOperation, ChangeArgs, CmanArgs, Commands, DbArgs,
+    DbCommands, DeleteArgs, FieldType, GetArgs, InitArgs, KeyAlgorithm, LsArgs, OtpArgs,
+    OutputFormat, PassphraseArgs, PolicyArgs, PullArgs, SecretType, SortKey, parse_custom_key,
 };
+use crate::util::clipboard;
+use crate::util::config::{self as settings_file, Config, GeneratorConfig, Vault, settings};
 use crate::util::errors::{CMError, CustomError};
 use crate::util::ioutils::{
-    self, get_multiple_selections_from_terminal, get_terminal_input,
-    get_terminal_input_with_suggestions, get_user_confirmation,
+    self, get_multiline_input, get_multiple_selections_from_terminal, get_private_input,
+    get_terminal_input, get_terminal_input_with_suggestions, get_user_confirmation,
 };
+use crate::util::otp::{OtpKind, OtpSecret};
 use crate::util::passgen;
+use crate::util::secret::SecretString;
+use crate::util::sshkeys;
 
 use clap::CommandFactory;
 use clap_complete::generate;
@@ -18,96 +26,497 @@ use rusqlite::Connection;
 use std::env::home_dir;
 use std::env::var_os;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::path::Path;
 
 mod add;
+mod agent;
+mod attach;
+mod audit;
 mod change;
+mod config;
+mod database;
 mod delete;
+mod generate;
 mod get;
+mod history;
+mod keyfile;
+mod otp;
+mod search;
+mod trash;
+mod vault;
 
-use get::get_account_from_user;
-use get::get_api_from_user;
+use get::get_secret_from_user;
 
 type Result = std::result::Result<(), CMError>;
 
 const DB_ENV_VAR: &str = "CMAN_DBFILE";
 const REMOTE_DB_ENV_VAR: &str = "CMAN_DBURL";
+const KEYFILE_ENV_VAR: &str = "CMAN_KEYFILE";
 
 pub fn run_command(args: &CmanArgs) -> Result {
-    if let Commands::Init(args) = &args.command {
-        return run_init(args);
+    let mut config = Config::load()?;
+    let mut cmd_settings = config.clone();
+    if let Some(format) = args.format {
+        cmd_settings.output = Some(format);
+    }
+    if let Commands::Get(GetArgs {
+        clip_timeout: Some(timeout),
+        ..
+    }) = &args.command
+    {
+        cmd_settings.clip_timeout = Some(*timeout);
+    }
+    match &args.command {
+        Commands::Add(a) => {
+            apply_policy_args(&a.policy, &mut cmd_settings.generator);
+            apply_passphrase_args(&a.passphrase, &mut cmd_settings.generator);
+        }
+        Commands::Change(a) => {
+            apply_policy_args(&a.policy, &mut cmd_settings.generator);
+            apply_passphrase_args(&a.passphrase, &mut cmd_settings.generator);
+        }
+        Commands::Generate(a) => {
+            apply_policy_args(&a.policy, &mut cmd_settings.generator);
+            apply_passphrase_args(&a.passphrase, &mut cmd_settings.generator);
+        }
+        _ => {}
+    }
+    settings_file::init_settings(cmd_settings);
+    set_password_option(args);
+
+    if let Commands::Vault(a) = &args.command {
+        return vault::run_vault(a, &mut config);
+    }
+    if let Commands::Config(a) = &args.command {
+        return config::run_config(a, &mut config);
+    }
+    if let Commands::Agent(a) = &args.command {
+        return agent::run_agent(a);
+    }
+    if let Commands::Lock = &args.command {
+        return agent::run_lock();
+    }
+    if let Commands::Init(a) = &args.command {
+        return run_init(a, &args.vault, &mut config);
+    }
+    if let Commands::Clip(a) = &args.command {
+        return clipboard::serve_clipboard(a.timeout);
+    }
+    if let Commands::Generate(a) = &args.command {
+        return generate::run_generate(a);
     }
     if let Commands::Completions { shell } = &args.command {
         let mut cmd = CmanArgs::command();
         generate(*shell, &mut cmd, "cman", &mut std::io::stdout());
         return Ok(());
     }
-    if let Commands::Pull(args) = &args.command {
-        return run_pull(args);
+    if let Commands::Db(DbArgs {
+        command: DbCommands::Benchmark(a),
+    }) = &args.command
+    {
+        return database::run_benchmark(a);
+    }
+    if let Commands::Keyfile(a) = &args.command {
+        return keyfile::run_keyfile(a);
+    }
+    if let Some(k) = get_keyfile(&args.vault, &config)? {
+        key::set_keyfile(Path::new(&k));
+    }
+    if let Commands::Pull(a) = &args.command {
+        return run_pull(a, &args.vault, &config);
     }
 
-    let dbpath = match get_db_path_from_env() {
-        Some(p) => p,
-        None => return Err(CustomError::new("Could not get Database file path").into()),
-    };
+    let dbpath = get_db_path(&args.vault, &config)?;
+    if let Commands::Db(a) = &args.command {
+        // pending migrations have to stay visible to the db subcommands so the database is
+        // opened without upgrading it first.
+        let dbcon = db::open_db_con(&dbpath)?;
+        return database::run_db(a, &dbcon);
+    }
     let dbcon = db::get_db_con(&dbpath)?;
 
     match &args.command {
         Commands::Add(a) => add::run_add(a, &dbcon),
         Commands::Get(a) => get::run_get(a, &dbcon),
+        Commands::Change(a) if a.secret.as_deref() == Some("master") => {
+            change::run_change_master(a, &args.vault, &mut config, &dbcon)
+        }
         Commands::Change(a) => change::run_change(a, &dbcon),
         Commands::Delete(a) => delete::run_delete(a, &dbcon),
         Commands::Ls(a) => run_list(a, &dbcon),
+        Commands::Search(a) => search::run_search(a, &dbcon),
+        Commands::History(a) => history::run_history(a, &dbcon),
+        Commands::Log(a) => audit::run_log(a, &dbcon),
+        Commands::Restore(a) => trash::run_restore(a, &dbcon),
+        Commands::Trash(a) => trash::run_trash(a, &dbcon),
+        Commands::Attach(a) => attach::run_attach(a, &dbcon),
+        Commands::Attachments(a) => attach::run_attachments(a, &dbcon),
+        Commands::Otp(a) => otp::run_otp(a, &dbcon),
         _ => Ok(()),
     }
 }
 
-fn run_init(args: &InitArgs) -> Result {
-    let path = match &args.path {
-        Some(p) => p.clone(),
-        None => match get_db_path_from_env() {
+/// Overrides the generator settings with the password policy given on the command line.
+fn apply_policy_args(args: &PolicyArgs, generator: &mut GeneratorConfig) {
+    for (off, min, class, class_min) in [
+        (
+            args.no_uppercase,
+            args.min_uppercase,
+            &mut generator.uppercase,
+            &mut generator.min_uppercase,
+        ),
+        (
+            args.no_lowercase,
+            args.min_lowercase,
+            &mut generator.lowercase,
+            &mut generator.min_lowercase,
+        ),
+        (
+            args.no_digits,
+            args.min_digits,
+            &mut generator.digits,
+            &mut generator.min_digits,
+        ),
+        (
+            args.no_symbols,
+            args.min_symbols,
+            &mut generator.symbols,
+            &mut generator.min_symbols,
+        ),
+    ] {
+        // a class left out also drops the minimum the config may ask for.
+        if off {
+            *class = Some(false);
+            *class_min = None;
+        }
+        if min.is_some() {
+            *class_min = min;
+        }
+    }
+    if args.exclude.is_some() {
+        generator.exclude = args.exclude.clone();
+    }
+    if args.avoid_ambiguous {
+        generator.avoid_ambiguous = Some(true);
+    }
+    if args.alphabet.is_some() {
+        generator.alphabet = args.alphabet.clone();
+    }
+}
+
+/// Overrides the generator settings with the passphrase options given on the command line. Any of
+/// them switches the generator to passphrases.
+fn apply_passphrase_args(args: &PassphraseArgs, generator: &mut GeneratorConfig) {
+    let mut given = args.passphrase;
+    if args.words.is_some() {
+        generator.words = args.words;
+        given = true;
+    }
+    if args.separator.is_some() {
+        generator.separator = args.separator.clone();
+        given = true;
+    }
+    for (set, option) in [
+        (args.capitalize, &mut generator.capitalize),
+        (args.add_digit, &mut generator.add_digit),
+        (args.add_symbol, &mut generator.add_symbol),
+    ] {
+        if set {
+            *option = Some(true);
+            given = true;
+        }
+    }
+    if given {
+        generator.passphrase = Some(true);
+    }
+}
+
+/// Generates a password for a login following the generator settings and tells the user how
+/// strong a passphrase is.
+fn generate_password(passlen: Option<usize>) -> std::result::Result<SecretString, CMError> {
+    let generator = passgen::Generator::from_settings(passlen)?;
+    let pass = generator.generate()?;
+    if let passgen::Generator::Passphrase(p) = &generator {
+        println!(
+            "Generated a passphrase with about {:.0} bits of entropy",
+            p.entropy()
+        );
+    }
+    Ok(pass)
+}
+
+/// Hands the master password source chosen on the command line to the database module.
+fn set_password_option(args: &CmanArgs) {
+    let option = if let Some(fd) = args.password_fd {
+        PasswordOption::Fd(fd)
+    } else if args.password_stdin {
+        PasswordOption::Stdin
+    } else if let Some(name) = &args.password_env {
+        PasswordOption::Env(name.clone())
+    } else {
+        return;
+    };
+    key::set_password_option(option);
+}
+
+fn run_init(args: &InitArgs, vault: &Option<String>, config: &mut Config) -> Result {
+    // a vault that is not registered yet is registered with the path it is created at.
+    if let Some(name) = vault
+        && !config.vaults.contains_key(name)
+    {
+        let path = match &args.path {
+            Some(p) => vault::absolute_path(p)?,
             None => {
+                return Err(CustomError::new(&format!(
+                    "There is no vault named {}. Pass --path to create it or register an existing database with cman vault add",
+                    name
+                ))
+                .into());
+            }
+        };
+        let keyfile = absolute_keyfile(&args.keyfile)?;
+        let params = database::cipher_params(&args.cipher, CipherParams::default())?;
+        db::create_new_db(
+            &path,
+            keyfile.as_deref().map(Path::new),
+            !args.no_password,
+            &params,
+        )?;
+        vault::register_vault(name, &path, None, config);
+        if let Some(v) = config.vaults.get_mut(name) {
+            v.keyfile = keyfile;
+        }
+        config.save()?;
+        println!("Registered vault {} at {}", name, path);
+        return Ok(());
+    }
+
+    let path = match &args.path {
+        Some(p) => {
+            if let Some(name) = vault {
+                return Err(CustomError::new(&format!(
+                    "Vault {} is already registered at {}",
+                    name,
+                    config.get_vault(name)?.path
+                ))
+                .into());
+            }
+            p.clone()
+        }
+        None => match get_db_path(vault, config) {
+            Err(_) => {
                 return Err(CustomError::new(
                     "Could not get Database path. Try passing --path argument.",
                 )
                 .into());
             }
-            Some(p) => p,
+            Ok(p) => p,
         },
     };
 
-    db::create_new_db(&path)?;
+    let keyfile = absolute_keyfile(&args.keyfile)?;
+    let params = database::cipher_params(&args.cipher, CipherParams::default())?;
+    db::create_new_db(
+        &path,
+        keyfile.as_deref().map(Path::new),
+        !args.no_password,
+        &params,
+    )?;
+    match selected_vault_name(vault, config) {
+        Some(name) if args.path.is_none() => {
+            if let Some(v) = config.vaults.get_mut(&name) {
+                v.keyfile = keyfile;
+            }
+            config.save()?;
+        }
+        _ => {
+            if let Some(k) = keyfile {
+                println!(
+                    "Set ${} or run cman config set keyfile {} so that cman can find the keyfile",
+                    KEYFILE_ENV_VAR, k
+                );
+            }
+        }
+    }
     Ok(())
 }
 
+/// Keyfiles are remembered by absolute path so that they are found from any directory.
+fn absolute_keyfile(keyfile: &Option<String>) -> std::result::Result<Option<String>, CMError> {
+    match keyfile {
+        Some(k) => Ok(Some(vault::absolute_path(k)?)),
+        None => Ok(None),
+    }
+}
+
+/// The keyfile of the database that is opened: $CMAN_KEYFILE, then the keyfile of the selected
+/// vault or, without a vault, the keyfile setting.
+fn get_keyfile(
+    vault: &Option<String>,
+    config: &Config,
+) -> std::result::Result<Option<String>, CMError> {
+    if let Some(k) = var_os(KEYFILE_ENV_VAR).and_then(|v| v.into_string().ok())
+        && !k.is_empty()
+    {
+        return Ok(Some(k));
+    }
+    match selected_vault(vault, config)? {
+        Some(v) => Ok(v.keyfile.clone()),
+        None => Ok(config.keyfile.clone()),
+    }
+}
+
 fn run_list(args: &LsArgs, dbcon: &Connection) -> Result {
-    let secret_type = args.secret_type.unwrap_or(SecretType::Login);
-    let results = match secret_type {
-        SecretType::Login => db::get_all_accounts_from_db(dbcon),
-        SecretType::Api => db::get_all_apikeys_from_db(dbcon),
-    }?;
+    let secret_type = secret_type_or_default(args.secret_type);
+    let mut results = db::get_all_secrets_from_db(secret_type, dbcon)?;
+
+    retain_tagged(&mut results, &args.tags);
+
+    match args.sort {
+        Some(SortKey::Name) => results.sort_by_key(|s| s.get_name()),
+        // timestamps share one format so comparing them as strings orders them by time. Reversing
+        // the Option ordering puts the newest first and secrets without a timestamp last.
+        Some(SortKey::Created) => results.sort_by(|a, b| b.created_at().cmp(&a.created_at())),
+        Some(SortKey::Updated) => results.sort_by(|a, b| b.updated_at().cmp(&a.updated_at())),
+        Some(SortKey::Accessed) => results.sort_by(|a, b| b.accessed_at().cmp(&a.accessed_at())),
+        None => (),
+    }
 
-    if args.json {
+    let json = json_output(args.json);
+    // values are printed like with cman get, so each secret shown gets a get entry in the audit
+    // log. The access time is left alone so that --sort accessed is not upset by listing.
+    let tx = dbcon.unchecked_transaction()?;
+    for result in &results {
+        if json || !matches!(result, Secret::Note(_)) {
+            db::log_operation(
+                AuditOperation::Get,
+                Some(result.get_type()),
+                Some(&result.get_name()),
+                None,
+                &tx,
+            )?;
+        }
+    }
+    tx.commit()?;
+
+    if json {
         let json_str = serde_json::to_string_pretty(&results).unwrap_or("".to_string());
         println!("{}", json_str);
         return Ok(());
     }
 
     for result in results {
+        // note bodies can be long so only their names are listed.
+        if let Secret::Note(_) = result {
+            ioutils::print_result("Name", &result.get_name());
+            continue;
+        }
         result.print();
     }
     Ok(())
 }
 
-fn run_pull(args: &PullArgs) -> Result {
-    let url = match &args.url {
-        Some(u) => u.clone(),
+/// Trashed secrets keep their names, so a name cannot be reused until the trash is purged.
+fn check_not_in_trash(sec_type: SecretType, sec_name: &str, dbcon: &Connection) -> Result {
+    let trashed = db::check_secret_in_trash(sec_type, sec_name, dbcon)?;
+    if trashed {
+        let (type_name, _) = type_names(sec_type);
+        return Err(CustomError::new(&format!(
+            "{} {} is in the trash. Restore it with cman restore {} or purge it with cman trash purge",
+            type_name, sec_name, sec_name
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// The type given with --type, otherwise the secret_type setting or login.
+fn secret_type_or_default(sec_type: Option<SecretType>) -> SecretType {
+    sec_type
+        .or(settings().secret_type)
+        .unwrap_or(SecretType::Login)
+}
+
+/// Resolves a secret name given on the command line to its type. Without --type or a type/name
+/// qualifier every secret type is searched. When several types share the name the user picks
+/// one, or the lookup fails if there is no terminal to ask on.
+fn resolve_secret(
+    name: &str,
+    sec_type: Option<SecretType>,
+    dbcon: &Connection,
+) -> std::result::Result<(SecretType, String), CMError> {
+    match db::lookup_secret(name, sec_type, dbcon)? {
+        SecretLookup::Found(t, name) => Ok((t, name)),
+        SecretLookup::NotFound(name) => {
+            Err(CustomError::new(&format!("No secret named {} exists", name)).into())
+        }
+        SecretLookup::Ambiguous(types, name) => {
+            let qualified: Vec<String> = types
+                .iter()
+                .map(|t| format!("{}/{}", type_flag(*t), name))
+                .collect();
+            if !std::io::stdin().is_terminal() {
+                return Err(CustomError::new(&format!(
+                    "More than one secret is named {}. Use one of {}",
+                    name,
+                    qualified.join(", ")
+                ))
+                .into());
+            }
+
+            let choice = get_terminal_input_with_suggestions(
+                &format!("More than one secret is named {}. Choose one", name),
+                qualified.clone(),
+            )?;
+            let index = qualified.iter().position(|q| *q == choice).unwrap_or(0);
+            Ok((types[index], name))
+        }
+    }
+}
+
+/// Whether to print json, because --json was given or because of the output setting.
+fn json_output(json: bool) -> bool {
+    json || settings().output == Some(OutputFormat::Json)
+}
+
+/// How a secret type is named in messages, singular and plural.
+fn type_names(sec_type: SecretType) -> (&'static str, &'static str) {
+    match sec_type {
+        SecretType::Login => ("Account", "accounts"),
+        SecretType::Api => ("API Key", "api keys"),
+        SecretType::Ssh => ("SSH Key", "ssh keys"),
+        SecretType::Note => ("Note", "notes"),
+    }
+}
+
+/// The value of the --type argument that selects a secret type.
+fn type_flag(sec_type: SecretType) -> &'static str {
+    match sec_type {
+        SecretType::Login => "login",
+        SecretType::Api => "api",
+        SecretType::Ssh => "ssh",
+        SecretType::Note => "note",
+    }
+}
+
+/// Keeps only the secrets that have all of the given tags.
+fn retain_tagged(secrets: &mut Vec<Secret>, tags: &[String]) {
+    secrets.retain(|secret| tags.iter().all(|tag| secret.get_tags().contains(tag)));
+}
+
+fn run_pull(args: &PullArgs, vault: &Option<String>, config: &Config) -> Result {
+    let selected = selected_vault(vault, config)?;
+    let vault_url = selected.and_then(|v| v.url.clone());
+
+    let url = match args.url.clone().or(vault_url) {
+        Some(u) => u,
         None => {
             let env_url = var_os(REMOTE_DB_ENV_VAR).and_then(|v| v.into_string().ok());
-            match env_url {
+            match env_url.or(config.remote_url.clone()) {
                 Some(u) => u,
                 None => {
-                    return Err(CustomError::new(&format!("Could not determine remote url to use. Either provide it via the --url flag or set it using the {} environmnet variable.", REMOTE_DB_ENV_VAR)).into());
+                    return Err(CustomError::new(&format!("Could not determine remote url to use. Either provide it via the --url flag, register it with the vault using cman vault add --url or set it using the {} environmnet variable.", REMOTE_DB_ENV_VAR)).into());
                 }
             }
         }
@@ -116,13 +525,10 @@ fn run_pull(args: &PullArgs) -> Result {
     let mut noout = false;
     let dbpath = match &args.out {
         Some(p) => p.clone(),
-        None => match get_db_path_from_env() {
-            Some(p) => {
-                noout = true;
-                p
-            }
-            None => return Err(CustomError::new("Could not get Database file path").into()),
-        },
+        None => {
+            noout = true;
+            get_db_path(vault, config)?
+        }
     };
 
     if noout {
@@ -138,6 +544,8 @@ fn run_pull(args: &PullArgs) -> Result {
     let spinner = ioutils::new_spinner("Fetching Database......".into());
 
     let client = reqwest::blocking::Client::new();
+    // fetched first so that a failure leaves the local database alone.
+    let params = fetch_cipher_params(&client, &url)?;
     let mut response = client.get(url).send()?;
 
     let mut db = std::fs::OpenOptions::new()
@@ -145,14 +553,97 @@ fn run_pull(args: &PullArgs) -> Result {
         .write(true)
         .create(true)
         .truncate(true)
-        .open(dbpath)?;
+        .open(&dbpath)?;
 
     std::io::copy(&mut response, &mut db)?;
+    drop(db);
 
     spinner.finish_with_message("Pull Done");
+
+    // the local parameters belong to the database that was just replaced.
+    match params {
+        Some(p) => p.save(&dbpath)?,
+        None => {
+            eprintln!(
+                "The remote has no cipher parameters next to the database, the SQLCipher defaults are used"
+            );
+            CipherParams::remove(&dbpath)?;
+        }
+    }
+
+    // the pull is recorded in the audit log of the pulled copy only if it opens without asking for
+    // the password and is up to date, so that pulling never prompts nor migrates it.
+    let recorded = match db::open_db_con_without_prompt(&dbpath) {
+        Ok(Some(dbcon)) => {
+            db::migrations::get_pending_migrations(&dbcon).is_ok_and(|pending| pending.is_empty())
+                && db::log_operation(AuditOperation::Pull, None, None, None, &dbcon).is_ok()
+        }
+        _ => false,
+    };
+    if !recorded {
+        eprintln!(
+            "The pull is not recorded in the audit log since the pulled database is not unlocked"
+        );
+    }
     Ok(())
 }
 
+/// The cipher parameters kept next to the database at url, the url with .cipher appended. None if
+/// the remote has none, in which case the database uses the defaults. Object stores such as S3 and
+/// GCS answer 403 rather than 404 for a missing file, so both count as none.
+fn fetch_cipher_params(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> std::result::Result<Option<CipherParams>, CMError> {
+    let params_url = format!("{}.cipher", url);
+    let response = client.get(&params_url).send()?;
+    if matches!(
+        response.status(),
+        reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::FORBIDDEN
+    ) {
+        return Ok(None);
+    }
+    let contents = response.error_for_status()?.text()?;
+    CipherParams::parse(&contents, &params_url).map(Some)
+}
+
+/// The database cman works on: the vault given with --vault, $CMAN_DBFILE, the default vault, the
+/// db_path setting or $HOME/.creds.db in that order.
+fn get_db_path(vault: &Option<String>, config: &Config) -> std::result::Result<String, CMError> {
+    if let Some(v) = selected_vault(vault, config)? {
+        return Ok(v.path.clone());
+    }
+    if let Some(p) = get_db_path_from_env() {
+        return Ok(p);
+    }
+    if let Some(p) = &config.db_path {
+        return Ok(p.clone());
+    }
+    match get_home_db_path() {
+        Some(p) => Ok(p),
+        None => Err(CustomError::new("Could not get Database file path").into()),
+    }
+}
+
+/// The vault given with --vault, or the default vault unless $CMAN_DBFILE overrides it.
+fn selected_vault<'a>(
+    vault: &Option<String>,
+    config: &'a Config,
+) -> std::result::Result<Option<&'a Vault>, CMError> {
+    match selected_vault_name(vault, config) {
+        Some(name) => Ok(Some(config.get_vault(&name)?)),
+        None => Ok(None),
+    }
+}
+
+fn selected_vault_name(vault: &Option<String>, config: &Config) -> Option<String> {
+    match vault {
+        Some(name) => Some(name.clone()),
+        None if get_db_path_from_env().is_some() => None,
+        None => config.default_vault.clone(),
+    }
+}
+
 fn get_db_path_from_env() -> Option<String> {
     let path = var_os(DB_ENV_VAR).and_then(|v| v.into_string().ok());
 
@@ -161,7 +652,10 @@ fn get_db_path_from_env() -> Option<String> {
     {
         return Some(credman_path);
     }
+    None
+}
 
+fn get_home_db_path() -> Option<String> {
     let home = home_dir();
     if let Some(home_path) = home {
         let creds_path_buf = home_path.join(".creds.db");
@@ -170,3 +664,61 @@ fn get_db_path_from_env() -> Option<String> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answers a single http request with `status` and `body`, returning the url of the database
+    /// whose parameters are fetched.
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/vault.db", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    fn fetch(
+        status: &'static str,
+        body: &'static str,
+    ) -> std::result::Result<Option<CipherParams>, CMError> {
+        let client = reqwest::blocking::Client::new();
+        fetch_cipher_params(&client, &serve_once(status, body))
+    }
+
+    #[test]
+    fn cipher_params_are_fetched() {
+        let body = r#"{"kdf_iter": 1000, "kdf_algorithm": "sha256", "hmac_algorithm": "sha1", "page_size": 8192}"#;
+        let params = fetch("200 OK", body).unwrap().unwrap();
+        assert_eq!(params.kdf_iter, 1000);
+        assert_eq!(params.page_size, 8192);
+    }
+
+    #[test]
+    fn missing_cipher_params_use_the_defaults() {
+        // object stores answer 403 for files that do not exist.
+        assert!(fetch("404 Not Found", "").unwrap().is_none());
+        assert!(fetch("403 Forbidden", "").unwrap().is_none());
+    }
+
+    #[test]
+    fn other_failures_stop_the_pull() {
+        assert!(fetch("500 Internal Server Error", "").is_err());
+        assert!(fetch("401 Unauthorized", "").is_err());
+        assert!(fetch("200 OK", "not json").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::commands::*;
 
 pub fn run_add(args: &AddArgs, dbcon: &Connection) -> Result {
-    let sec_type = args.secret_type.unwrap_or(SecretType::Login);
+    let sec_type = secret_type_or_default(args.secret_type);
     let sec_name = &args.secret;
     if sec_name == "master" {
         return Err(CustomError::new(
@@ -9,13 +9,19 @@ pub fn run_add(args: &AddArgs, dbcon: &Connection) -> Result {
         )
         .into());
     } else if args.batch {
-        return add_secrets_from_batch(sec_name, args.passlen, dbcon);
+        return add_secrets_from_batch(sec_name, args.passlen, &args.tags, dbcon);
     }
+    check_not_in_trash(sec_type, sec_name, dbcon)?;
 
     match sec_type {
         SecretType::Login => add_new_acc(sec_name, args.passlen, args.no_auto, dbcon)?,
         SecretType::Api => add_new_api(sec_name, dbcon)?,
+        SecretType::Ssh => add_new_ssh(sec_name, args, dbcon)?,
+        SecretType::Note => add_new_note(sec_name, dbcon)?,
     };
+    if !args.tags.is_empty() {
+        db::add_tags_to_secret(sec_type, sec_name, &args.tags, dbcon)?;
+    }
     println!("Added Successfully");
     Ok(())
 }
@@ -25,12 +31,12 @@ fn add_new_acc(name: &str, passlen: Option<usize>, noautopass: bool, dbcon: &Con
     if exists {
         return Err(CustomError::new(&format!("Account {} already exists", name)).into());
     }
-    let user_name = get_terminal_input("Enter username for the account", false, false)?;
+    let user_name = get_terminal_input("Enter username for the account")?;
 
     let pass = if noautopass {
-        get_terminal_input("Enter Password", true, true)?
+        get_private_input("Enter Password", true)?
     } else {
-        passgen::get_random_pass(passlen)?
+        generate_password(passlen)?
     };
 
     db::add_account_to_db(
@@ -38,6 +44,7 @@ fn add_new_acc(name: &str, passlen: Option<usize>, noautopass: bool, dbcon: &Con
             account_name: name.to_string(),
             user_name,
             password: pass,
+            ..Default::default()
         },
         dbcon,
     )?;
@@ -49,27 +56,94 @@ fn add_new_api(name: &str, dbcon: &Connection) -> Result {
     if exists {
         return Err(CustomError::new(&format!("API Key {} already exists", name)).into());
     }
-    let user_name = get_terminal_input(
-        "Enter username for the account associated with API Key (if any)",
-        false,
-        false,
-    )?;
-    let desc = get_terminal_input("Enter a short description for the API key", false, false)?;
-    let apikey = get_terminal_input("Enter API Key", false, false)?;
+    let user_name =
+        get_terminal_input("Enter username for the account associated with API Key (if any)")?;
+    let desc = get_terminal_input("Enter a short description for the API key")?;
+    let apikey = get_terminal_input("Enter API Key")?;
 
     db::add_apikey_to_db(
         &APIObj {
             api_name: name.to_string(),
             description: desc,
             user_name,
-            api_key: apikey,
+            api_key: apikey.into(),
+            ..Default::default()
         },
         dbcon,
     )?;
     Ok(())
 }
 
-fn add_secrets_from_batch(batch_file: &str, passlen: Option<usize>, dbcon: &Connection) -> Result {
+fn add_new_ssh(name: &str, args: &AddArgs, dbcon: &Connection) -> Result {
+    let exists = db::check_ssh_key_exists(name, dbcon)?;
+    if exists {
+        return Err(CustomError::new(&format!("SSH Key {} already exists", name)).into());
+    }
+
+    let (keypair, passphrase) = match &args.import {
+        Some(path) => {
+            let pem = SecretString::new(std::fs::read_to_string(path)?);
+            let passphrase = if sshkeys::is_encrypted(pem.expose())? {
+                get_private_input("Enter the passphrase of the private key", false)?
+            } else {
+                SecretString::default()
+            };
+            let keypair = sshkeys::read_keypair(pem.expose(), passphrase.expose())?;
+            (keypair, passphrase)
+        }
+        None => {
+            let passphrase = get_private_input(
+                "Enter a passphrase for the private key (leave empty for none)",
+                true,
+            )?;
+            let comment = args.comment.clone().unwrap_or(name.to_string());
+            let algorithm = args.generate.unwrap_or(KeyAlgorithm::Ed25519);
+            let keypair = sshkeys::generate_keypair(algorithm, &comment, passphrase.expose())?;
+            (keypair, passphrase)
+        }
+    };
+
+    db::add_ssh_key_to_db(
+        &SshObj {
+            ssh_name: name.to_string(),
+            comment: args.comment.clone().unwrap_or(keypair.comment),
+            public_key: keypair.public_key,
+            private_key: keypair.private_key,
+            passphrase,
+            ..Default::default()
+        },
+        dbcon,
+    )?;
+    Ok(())
+}
+
+fn add_new_note(name: &str, dbcon: &Connection) -> Result {
+    let exists = db::check_note_exists(name, dbcon)?;
+    if exists {
+        return Err(CustomError::new(&format!("Note {} already exists", name)).into());
+    }
+    let body = get_multiline_input("Enter the note", "")?;
+    if body.expose().trim().is_empty() {
+        return Err(CustomError::new("The note cannot be empty").into());
+    }
+
+    db::add_note_to_db(
+        &NoteObj {
+            note_name: name.to_string(),
+            body,
+            ..Default::default()
+        },
+        dbcon,
+    )?;
+    Ok(())
+}
+
+fn add_secrets_from_batch(
+    batch_file: &str,
+    passlen: Option<usize>,
+    tags: &[String],
+    dbcon: &Connection,
+) -> Result {
     let file = File::open(batch_file)?;
     let reader = BufReader::new(file);
     let mut lineno = 1;
@@ -88,13 +162,19 @@ fn add_secrets_from_batch(batch_file: &str, passlen: Option<usize>, dbcon: &Conn
         if fields[0] == "login" {
             let result = add_acc_from_file_line(dbcon, &fields, lineno, passlen);
             match result {
-                Ok(name) => successfull.push(name),
+                Ok(name) => {
+                    db::add_tags_to_secret(SecretType::Login, &name, tags, dbcon)?;
+                    successfull.push(name)
+                }
                 Err(e) => errors.push(e),
             }
         } else if fields[0] == "api" {
             let result = add_api_from_file_line(dbcon, &fields, lineno);
             match result {
-                Ok(name) => successfull.push(name),
+                Ok(name) => {
+                    db::add_tags_to_secret(SecretType::Api, &name, tags, dbcon)?;
+                    successfull.push(name)
+                }
                 Err(e) => errors.push(e),
             }
         } else {
@@ -127,14 +207,15 @@ fn add_acc_from_file_line(
     lineno: i32,
     passlen: Option<usize>,
 ) -> std::result::Result<String, CMError> {
-    if fields.len() != 4 {
+    if fields.len() < 4 {
         return Err(CustomError::new(&format!("Line {}: Wrong number of fields", lineno)).into());
     }
     let (account_name, user_name) = (fields[1], fields[2]);
+    let (notes, custom_fields) = parse_extra_fields(&fields[4..], lineno)?;
     let pass = if fields[3] == "?" {
         passgen::get_random_pass(passlen)?
     } else {
-        fields[3].to_string()
+        fields[3].into()
     };
 
     let exists = db::check_account_exists(account_name, dbcon)?;
@@ -144,6 +225,12 @@ fn add_acc_from_file_line(
             lineno, account_name
         ))
         .into());
+    } else if db::check_secret_in_trash(SecretType::Login, account_name, dbcon)? {
+        return Err(CustomError::new(&format!(
+            "Line {}: Account {} is in the trash",
+            lineno, account_name
+        ))
+        .into());
     } else if account_name == "master" {
         return Err(
             CustomError::new(&format!("Line {}: Account name cannot be master.", lineno)).into(),
@@ -162,6 +249,9 @@ fn add_acc_from_file_line(
         account_name: account_name.to_string(),
         user_name: user_name.to_string(),
         password: pass,
+        notes,
+        custom_fields,
+        ..Default::default()
     };
 
     db::add_account_to_db(&acc, dbcon)?;
@@ -174,11 +264,12 @@ fn add_api_from_file_line(
     fields: &[&str],
     lineno: i32,
 ) -> std::result::Result<String, CMError> {
-    if fields.len() != 5 {
+    if fields.len() < 5 {
         return Err(CustomError::new(&format!("Line {}: Wrong number of fields", lineno)).into());
     }
 
     let (api_name, user_name, description, api_key) = (fields[1], fields[2], fields[3], fields[4]);
+    let (notes, custom_fields) = parse_extra_fields(&fields[5..], lineno)?;
 
     let exists = db::check_apikey_exists(fields[1], dbcon)?;
     if exists {
@@ -187,6 +278,12 @@ fn add_api_from_file_line(
             lineno, api_name
         ))
         .into());
+    } else if db::check_secret_in_trash(SecretType::Api, api_name, dbcon)? {
+        return Err(CustomError::new(&format!(
+            "Line {}: API Key {} is in the trash",
+            lineno, api_name
+        ))
+        .into());
     } else if api_name == "master" {
         return Err(
             CustomError::new(&format!("Line {}: Api name cannot be master.", lineno)).into(),
@@ -203,10 +300,50 @@ fn add_api_from_file_line(
         api_name: api_name.to_string(),
         user_name: user_name.to_string(),
         description: description.to_string(),
-        api_key: api_key.to_string(),
+        api_key: api_key.into(),
+        notes,
+        custom_fields,
+        ..Default::default()
     };
 
     db::add_apikey_to_db(&api, dbcon)?;
 
     Ok(api.api_name)
 }
+
+/// Parses the optional fields that can follow the required ones on a batch file line. notes=<text>
+/// sets the notes of the secret, key=value adds a custom field and !key=value adds a sensitive one.
+fn parse_extra_fields(
+    fields: &[&str],
+    lineno: i32,
+) -> std::result::Result<(String, Vec<CustomField>), CMError> {
+    let mut notes = String::new();
+    let mut custom_fields: Vec<CustomField> = Vec::new();
+
+    for field in fields {
+        let Some((key, value)) = field.split_once('=') else {
+            return Err(CustomError::new(&format!(
+                "Line {}: Expected key=value but got {}",
+                lineno, field
+            ))
+            .into());
+        };
+
+        if key == "notes" {
+            notes = value.to_string();
+            continue;
+        }
+        let (key, sensitive) = match key.strip_prefix('!') {
+            Some(k) => (k, true),
+            None => (key, false),
+        };
+        let key = parse_custom_key(key)
+            .map_err(|e| CustomError::new(&format!("Line {}: {}", lineno, e)))?;
+        custom_fields.push(CustomField {
+            key,
+            value: value.into(),
+            sensitive,
+        });
+    }
+    Ok((notes, custom_fields))
+}
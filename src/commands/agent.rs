@@ -0,0 +1,56 @@
+use crate::agent::{self, DEFAULT_TIMEOUT};
+use crate::commands::*;
+use crate::util::argparser::AgentArgs;
+
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+pub fn run_agent(args: &AgentArgs) -> Result {
+    let timeout = args.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let socket = agent::socket_path()?;
+    if agent::is_running() {
+        println!("An agent is already running at {}", socket.display());
+        return Ok(());
+    }
+
+    if args.foreground {
+        println!("Agent listening on {}", socket.display());
+        return agent::serve(timeout);
+    }
+
+    // the agent is the same binary started again in the foreground, detached from this terminal.
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(["agent", "--foreground", "--timeout", &timeout.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()?;
+
+    for _ in 0..50 {
+        if agent::is_running() {
+            println!(
+                "Agent started at {} and stops after {} idle seconds",
+                socket.display(),
+                timeout
+            );
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err(CustomError::new("The agent did not start. Try cman agent --foreground to see why").into())
+}
+
+pub fn run_lock() -> Result {
+    if agent::lock()? {
+        println!("The agent has forgotten all keys and stopped");
+    } else {
+        println!("No agent is running");
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use crate::commands::*;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+pub fn run_attach(args: &AttachArgs, dbcon: &Connection) -> Result {
+    let (sec_type, sec_name) = resolve_secret(&args.secret, args.secret_type, dbcon)?;
+    check_exists(sec_type, &sec_name, dbcon)?;
+
+    let file_name = match &args.name {
+        Some(n) => n.clone(),
+        None => match Path::new(&args.file).file_name() {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => {
+                return Err(CustomError::new(&format!(
+                    "{} is not a file that can be attached",
+                    args.file
+                ))
+                .into());
+            }
+        },
+    };
+    if file_name.is_empty() {
+        return Err(CustomError::new("The attachment name cannot be empty").into());
+    }
+
+    let exists = db::check_attachment_exists(sec_type, &sec_name, &file_name, dbcon)?;
+    if exists {
+        return Err(CustomError::new(&format!(
+            "{} already has an attachment named {}. Remove it first with cman attachments {} --remove {}",
+            sec_name, file_name, sec_name, file_name
+        ))
+        .into());
+    }
+
+    let data = std::fs::read(&args.file)?;
+    db::add_attachment_to_db(sec_type, &sec_name, &file_name, &data, dbcon)?;
+    println!("Attached {} to {}", file_name, sec_name);
+    Ok(())
+}
+
+pub fn run_attachments(args: &AttachmentsArgs, dbcon: &Connection) -> Result {
+    let (sec_type, sec_name) = match &args.secret {
+        Some(s) => resolve_secret(s, args.secret_type, dbcon)?,
+        None => {
+            let sec_type = secret_type_or_default(args.secret_type);
+            (
+                sec_type,
+                get_secret_from_user(sec_type, dbcon, &[])?.get_name(),
+            )
+        }
+    };
+    check_exists(sec_type, &sec_name, dbcon)?;
+
+    if let Some(file_name) = &args.remove {
+        let opt = get_user_confirmation(&format!(
+            "Are you sure you want to remove {} from {} (yes/no)",
+            file_name, sec_name
+        ))?;
+        if !opt {
+            return Ok(());
+        }
+        let removed = db::remove_attachment_from_db(sec_type, &sec_name, file_name, dbcon)?;
+        if removed == 0 {
+            return Err(CustomError::new(&format!(
+                "{} has no attachment named {}",
+                sec_name, file_name
+            ))
+            .into());
+        }
+        println!("Removed Successfully");
+        return Ok(());
+    }
+
+    let attachments = db::get_attachments_from_db(sec_type, &sec_name, dbcon)?;
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&attachments).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if attachments.is_empty() {
+        println!("{} has no attachments", sec_name);
+        return Ok(());
+    }
+    for attachment in attachments {
+        println!(
+            "{}  {} bytes  added {} UTC",
+            attachment.file_name, attachment.size, attachment.added_at
+        );
+    }
+    Ok(())
+}
+
+/// Writes an attachment to `out`, or to a file named after the attachment in the current
+/// directory. The file is created readable by the owner only and never replaces an existing one.
+pub fn extract_attachment(
+    secret: &Secret,
+    file_name: &str,
+    out: &Option<String>,
+    dbcon: &Connection,
+) -> Result {
+    let data =
+        db::get_attachment_data_from_db(secret.get_type(), &secret.get_name(), file_name, dbcon)?;
+    let out = out.clone().unwrap_or(file_name.to_string());
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&out).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AlreadyExists {
+            CustomError::new(&format!("{} already exists", out)).into()
+        } else {
+            CMError::from(e)
+        }
+    })?;
+    file.write_all(&data)?;
+
+    println!("Wrote {} to {}", file_name, out);
+    Ok(())
+}
+
+fn check_exists(sec_type: SecretType, sec_name: &str, dbcon: &Connection) -> Result {
+    let exists = db::check_secret_exists(sec_type, sec_name, dbcon)?;
+    if !exists {
+        let (type_name, _) = type_names(sec_type);
+        return Err(CustomError::new(&format!("{} {} does not exist", type_name, sec_name)).into());
+    }
+    Ok(())
+}
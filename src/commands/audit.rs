@@ -0,0 +1,43 @@
+use crate::commands::*;
+use crate::db::AuditFilter;
+use crate::util::argparser::LogArgs;
+
+pub fn run_log(args: &LogArgs, dbcon: &Connection) -> Result {
+    let filter = AuditFilter {
+        secret_name: args.secret.clone(),
+        sec_type: args.secret_type,
+        operation: args.operation,
+        since: args.since.clone(),
+        until: args.until.clone(),
+        limit: args.limit,
+    };
+    let entries = db::get_audit_log(&filter, dbcon)?;
+
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&entries).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No matching entries in the audit log");
+        return Ok(());
+    }
+    for entry in entries {
+        let secret = match (&entry.secret_type, &entry.secret_name) {
+            (Some(t), Some(name)) => format!("{}/{}", t, name),
+            _ => "-".to_string(),
+        };
+        let line = format!(
+            "{} UTC  {:<10} {:<7} {:<8} {}  {}",
+            entry.logged_at,
+            entry.actor,
+            entry.unlocked_with.as_deref().unwrap_or("-"),
+            entry.operation,
+            secret,
+            entry.field.unwrap_or_default()
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
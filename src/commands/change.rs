@@ -1,45 +1,93 @@
 use crate::commands::*;
 
-pub fn run_change(args: &ChangeArgs, dbcon: &Connection) -> Result {
-    let sec_type = args.secret_type.unwrap_or(SecretType::Login);
-    if let Some(s) = &args.secret
-        && s == "master"
-    {
-        db::change_db_password(dbcon)?;
-        println!("Master Password Changed Successfully");
+/// Changes the master password and keyfile. The new keyfile is remembered where cman looks for it
+/// so that the next command can still open the database.
+pub fn run_change_master(
+    args: &ChangeArgs,
+    vault: &Option<String>,
+    config: &mut Config,
+    dbcon: &Connection,
+) -> Result {
+    let keyfile = absolute_keyfile(&args.keyfile)?;
+    db::change_db_password(dbcon, keyfile.as_deref().map(Path::new), !args.no_password)?;
+    println!("Master Password Changed Successfully");
+
+    let old_keyfile = key::keyfile().map(|p| p.to_string_lossy().to_string());
+    if var_os(KEYFILE_ENV_VAR).is_some_and(|v| !v.is_empty()) {
+        if old_keyfile != keyfile {
+            match &keyfile {
+                Some(k) => println!("Point ${} to {} to open the database", KEYFILE_ENV_VAR, k),
+                None => println!("Unset ${} to open the database", KEYFILE_ENV_VAR),
+            }
+        }
         return Ok(());
     }
+    match selected_vault_name(vault, config) {
+        Some(name) => {
+            if let Some(v) = config.vaults.get_mut(&name)
+                && v.keyfile != keyfile
+            {
+                v.keyfile = keyfile;
+                config.save()?;
+            }
+        }
+        None => {
+            if config.keyfile != keyfile {
+                config.keyfile = keyfile;
+                config.save()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run_change(args: &ChangeArgs, dbcon: &Connection) -> Result {
+    let sec_type = secret_type_or_default(args.secret_type);
+    if args.keyfile.is_some() {
+        return Err(CustomError::new(
+            "A keyfile can only be given when changing the master password",
+        )
+        .into());
+    }
+
+    let (sec_type, sec_name) = match &args.secret {
+        Some(s) => {
+            let (t, name) = resolve_secret(s, args.secret_type, dbcon)?;
+            (t, Some(name))
+        }
+        None => (sec_type, None),
+    };
 
     match sec_type {
-        SecretType::Login => change_acc_field(args, dbcon)?,
-        SecretType::Api => change_api_field(args, dbcon)?,
+        SecretType::Login => change_acc_field(args, sec_name, dbcon)?,
+        SecretType::Api => change_api_field(args, sec_name, dbcon)?,
+        SecretType::Ssh => change_ssh_field(args, sec_name, dbcon)?,
+        SecretType::Note => change_note_field(args, sec_name, dbcon)?,
     };
     Ok(())
 }
 
-fn change_acc_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
-    let sec_name = match &args.secret {
-        Some(s) => s.clone(),
-        None => {
-            let account = get_account_from_user(dbcon)?;
-            if let Secret::Account(acc) = account {
-                acc.account_name.clone()
-            } else {
-                "".to_string()
-            }
-        }
+fn change_acc_field(args: &ChangeArgs, sec_name: Option<String>, dbcon: &Connection) -> Result {
+    let sec_name = match sec_name {
+        Some(s) => s,
+        None => get_secret_from_user(SecretType::Login, dbcon, &[])?.get_name(),
     };
 
     let exists = db::check_account_exists(&sec_name, dbcon)?;
     if !exists {
         return Err(CustomError::new(&format!("Account {} does not exist", sec_name)).into());
     }
-    let fieldtype = args.field.unwrap_or(FieldType::Pass);
-    let new_value = match fieldtype {
-        FieldType::User => get_terminal_input("Enter new user name", false, false)?,
+    if let Some(n) = args.restore {
+        return restore_from_history(SecretType::Login, &sec_name, n, dbcon);
+    }
+    if !args.add_tags.is_empty() || !args.remove_tags.is_empty() {
+        return change_tags(SecretType::Login, &sec_name, args, dbcon);
+    }
+    let fieldtype = args.field.clone().unwrap_or(FieldType::Pass);
+    let new_value: SecretString = match fieldtype {
+        FieldType::User => get_terminal_input("Enter new user name")?.into(),
         FieldType::Secname => {
-            let input =
-                get_terminal_input("Enter new name for the login credential", false, false)?;
+            let input = get_terminal_input("Enter new name for the login credential")?;
             let exists = db::check_account_exists(&input, dbcon)?;
             if exists {
                 return Err(CustomError::new(&format!(
@@ -54,7 +102,8 @@ fn change_acc_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
                 )
                 .into());
             }
-            input
+            check_not_in_trash(SecretType::Login, &input, dbcon)?;
+            input.into()
         }
         FieldType::Pass => {
             let opt =
@@ -65,11 +114,26 @@ fn change_acc_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
             }
 
             if args.no_auto {
-                get_terminal_input("Enter new password", true, true)?
+                get_private_input("Enter new password", true)?
             } else {
-                passgen::get_random_pass(args.passlen)?
+                generate_password(args.passlen)?
             }
         }
+        FieldType::Otp => {
+            let input = get_private_input(
+                "Enter the otpauth:// URI or base32 secret (leave empty to remove)",
+                false,
+            )?;
+            if input.expose().trim().is_empty() {
+                SecretString::default()
+            } else {
+                OtpSecret::parse(input.expose())?.to_uri()
+            }
+        }
+        FieldType::Notes => get_terminal_input("Enter new notes")?.into(),
+        FieldType::Custom(key) => {
+            return change_custom_field(SecretType::Login, &sec_name, &key, args.sensitive, dbcon);
+        }
         _ => {
             return Err(
                 CustomError::new("The given field is invalid for a login credential").into(),
@@ -77,31 +141,30 @@ fn change_acc_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
         }
     };
 
-    db::change_db_account_field(&sec_name, fieldtype, &new_value, dbcon)?;
+    db::change_db_account_field(&sec_name, &fieldtype, new_value.expose(), dbcon)?;
     println!("Changed Successfully");
     Ok(())
 }
 
-fn change_api_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
-    let sec_name = match &args.secret {
-        Some(s) => s.clone(),
-        None => {
-            let api_obj = get_api_from_user(dbcon)?;
-            if let Secret::API(api) = api_obj {
-                api.api_name.clone()
-            } else {
-                "".to_string()
-            }
-        }
+fn change_api_field(args: &ChangeArgs, sec_name: Option<String>, dbcon: &Connection) -> Result {
+    let sec_name = match sec_name {
+        Some(s) => s,
+        None => get_secret_from_user(SecretType::Api, dbcon, &[])?.get_name(),
     };
     let exists = db::check_apikey_exists(&sec_name, dbcon)?;
     if !exists {
         return Err(CustomError::new(&format!("API {} does not exist", sec_name)).into());
     }
-    let fieldtype = args.field.unwrap_or(FieldType::Key);
-    let new_value = match fieldtype {
+    if let Some(n) = args.restore {
+        return restore_from_history(SecretType::Api, &sec_name, n, dbcon);
+    }
+    if !args.add_tags.is_empty() || !args.remove_tags.is_empty() {
+        return change_tags(SecretType::Api, &sec_name, args, dbcon);
+    }
+    let fieldtype = args.field.clone().unwrap_or(FieldType::Key);
+    let new_value: SecretString = match fieldtype {
         FieldType::Secname => {
-            let input = get_terminal_input("Enter new name for the api key", false, false)?;
+            let input = get_terminal_input("Enter new name for the api key")?;
             let exists = db::check_apikey_exists(&input, dbcon)?;
             if exists {
                 return Err(CustomError::new(&format!(
@@ -116,17 +179,293 @@ fn change_api_field(args: &ChangeArgs, dbcon: &Connection) -> Result {
                 )
                 .into());
             }
-            input
+            check_not_in_trash(SecretType::Api, &input, dbcon)?;
+            input.into()
         }
-        FieldType::Desc => {
-            get_terminal_input("Enter new description for the API key", false, false)?
+        FieldType::Desc => get_terminal_input("Enter new description for the API key")?.into(),
+        FieldType::User => get_terminal_input("Enter new user name")?.into(),
+        FieldType::Key => get_terminal_input("Enter new API key")?.into(),
+        FieldType::Notes => get_terminal_input("Enter new notes")?.into(),
+        FieldType::Custom(key) => {
+            return change_custom_field(SecretType::Api, &sec_name, &key, args.sensitive, dbcon);
         }
-        FieldType::User => get_terminal_input("Enter new user name", false, false)?,
-        FieldType::Key => get_terminal_input("Enter new API key", false, false)?,
         _ => return Err(CustomError::new("The given field is invalid for an API key").into()),
     };
 
-    db::change_db_apikey_field(&sec_name, fieldtype, &new_value, dbcon)?;
+    db::change_db_apikey_field(&sec_name, &fieldtype, new_value.expose(), dbcon)?;
+    println!("Changed Successfully");
+    Ok(())
+}
+
+fn change_ssh_field(args: &ChangeArgs, sec_name: Option<String>, dbcon: &Connection) -> Result {
+    let sec_name = match sec_name {
+        Some(s) => s,
+        None => get_secret_from_user(SecretType::Ssh, dbcon, &[])?.get_name(),
+    };
+    let exists = db::check_ssh_key_exists(&sec_name, dbcon)?;
+    if !exists {
+        return Err(CustomError::new(&format!("SSH Key {} does not exist", sec_name)).into());
+    }
+    if let Some(n) = args.restore {
+        return restore_from_history(SecretType::Ssh, &sec_name, n, dbcon);
+    }
+    if !args.add_tags.is_empty() || !args.remove_tags.is_empty() {
+        return change_tags(SecretType::Ssh, &sec_name, args, dbcon);
+    }
+
+    let fieldtype = args.field.clone().unwrap_or(FieldType::Privkey);
+    let new_value = match fieldtype {
+        FieldType::Secname => {
+            let input = get_terminal_input("Enter new name for the ssh key")?;
+            let exists = db::check_ssh_key_exists(&input, dbcon)?;
+            if exists {
+                return Err(CustomError::new(&format!(
+                    "SSH Key with name {} already exists",
+                    input
+                ))
+                .into());
+            }
+            if input == "master" {
+                return Err(CustomError::new(
+                    "Cannot change name to \"master\" because it reserved for master password.",
+                )
+                .into());
+            }
+            check_not_in_trash(SecretType::Ssh, &input, dbcon)?;
+            input
+        }
+        FieldType::Comment => get_terminal_input("Enter new comment")?,
+        FieldType::Notes => get_terminal_input("Enter new notes")?,
+        FieldType::Privkey | FieldType::Passphrase => {
+            return change_ssh_keypair(&sec_name, &fieldtype, dbcon);
+        }
+        FieldType::Custom(key) => {
+            return change_custom_field(SecretType::Ssh, &sec_name, &key, args.sensitive, dbcon);
+        }
+        _ => return Err(CustomError::new("The given field is invalid for an ssh key").into()),
+    };
+
+    db::change_db_ssh_key_field(&sec_name, &fieldtype, &new_value, dbcon)?;
+    println!("Changed Successfully");
+    Ok(())
+}
+
+fn change_note_field(args: &ChangeArgs, sec_name: Option<String>, dbcon: &Connection) -> Result {
+    let sec_name = match sec_name {
+        Some(s) => s,
+        None => get_secret_from_user(SecretType::Note, dbcon, &[])?.get_name(),
+    };
+    let exists = db::check_note_exists(&sec_name, dbcon)?;
+    if !exists {
+        return Err(CustomError::new(&format!("Note {} does not exist", sec_name)).into());
+    }
+    if let Some(n) = args.restore {
+        return restore_from_history(SecretType::Note, &sec_name, n, dbcon);
+    }
+    if !args.add_tags.is_empty() || !args.remove_tags.is_empty() {
+        return change_tags(SecretType::Note, &sec_name, args, dbcon);
+    }
+
+    let fieldtype = args.field.clone().unwrap_or(FieldType::Body);
+    let new_value: SecretString = match fieldtype {
+        FieldType::Secname => {
+            let input = get_terminal_input("Enter new name for the note")?;
+            let exists = db::check_note_exists(&input, dbcon)?;
+            if exists {
+                return Err(
+                    CustomError::new(&format!("Note with name {} already exists", input)).into(),
+                );
+            }
+            if input == "master" {
+                return Err(CustomError::new(
+                    "Cannot change name to \"master\" because it reserved for master password.",
+                )
+                .into());
+            }
+            check_not_in_trash(SecretType::Note, &input, dbcon)?;
+            input.into()
+        }
+        FieldType::Body => {
+            let current = db::get_note_from_db(&sec_name, dbcon)?;
+            let body = current.get_field(&FieldType::Body);
+            let input = get_multiline_input("Edit the note", body.expose())?;
+            if input.expose().trim().is_empty() {
+                return Err(CustomError::new("The note cannot be empty").into());
+            }
+            input
+        }
+        FieldType::Notes => get_terminal_input("Enter new notes")?.into(),
+        FieldType::Custom(key) => {
+            return change_custom_field(SecretType::Note, &sec_name, &key, args.sensitive, dbcon);
+        }
+        _ => return Err(CustomError::new("The given field is invalid for a note").into()),
+    };
+
+    db::change_db_note_field(&sec_name, &fieldtype, new_value.expose(), dbcon)?;
+    println!("Changed Successfully");
+    Ok(())
+}
+
+/// Generates a new key pair for the ssh key or re-encrypts its private key with a new passphrase.
+fn change_ssh_keypair(sec_name: &str, field: &FieldType, dbcon: &Connection) -> Result {
+    let current = db::get_ssh_key_from_db(sec_name, dbcon)?;
+    let old_passphrase = current.get_field(&FieldType::Passphrase);
+
+    let (keypair, passphrase) = if *field == FieldType::Privkey {
+        let opt = get_user_confirmation(
+            "Are you sure you want to replace the key pair with a new one (yes/no)",
+        )?;
+        if !opt {
+            return Ok(());
+        }
+        let comment = current.get_field(&FieldType::Comment);
+        let keypair = sshkeys::generate_keypair(
+            KeyAlgorithm::Ed25519,
+            comment.expose(),
+            old_passphrase.expose(),
+        )?;
+        (keypair, old_passphrase)
+    } else {
+        let new_passphrase = get_private_input(
+            "Enter new passphrase for the private key (leave empty for none)",
+            true,
+        )?;
+        let private_key = current.get_field(&FieldType::Privkey);
+        let keypair = sshkeys::change_passphrase(
+            private_key.expose(),
+            old_passphrase.expose(),
+            new_passphrase.expose(),
+        )?;
+        (keypair, new_passphrase)
+    };
+
+    db::change_db_ssh_keypair(
+        sec_name,
+        &keypair.private_key,
+        &keypair.public_key,
+        &passphrase,
+        dbcon,
+    )?;
+    println!("Changed Successfully");
+    Ok(())
+}
+
+/// Restores a private key from the history. The stored passphrase is kept if it still unlocks the
+/// restored key, otherwise the user is asked for the right one.
+fn restore_ssh_key(
+    sec_name: &str,
+    private_key: &SecretString,
+    dbcon: &Connection,
+) -> std::result::Result<usize, CMError> {
+    let private_key = private_key.expose();
+    let mut passphrase = SecretString::default();
+    if sshkeys::is_encrypted(private_key)? {
+        let current = db::get_ssh_key_from_db(sec_name, dbcon)?;
+        passphrase = current.get_field(&FieldType::Passphrase);
+        if sshkeys::read_keypair(private_key, passphrase.expose()).is_err() {
+            passphrase =
+                get_private_input("Enter the passphrase of the restored private key", false)?;
+        }
+    }
+
+    let keypair = sshkeys::read_keypair(private_key, passphrase.expose())?;
+    let affected_rows = db::change_db_ssh_keypair(
+        sec_name,
+        &keypair.private_key,
+        &keypair.public_key,
+        &passphrase,
+        dbcon,
+    )?;
+    Ok(affected_rows)
+}
+
+fn restore_from_history(
+    sec_type: SecretType,
+    sec_name: &str,
+    n: usize,
+    dbcon: &Connection,
+) -> Result {
+    let history = db::get_secret_history(sec_type, sec_name, dbcon)?;
+    let entry = match n.checked_sub(1).and_then(|i| history.get(i)) {
+        Some(e) => e,
+        None => {
+            return Err(CustomError::new(&format!(
+                "{} has no history entry {}. Use cman history {} to list them.",
+                sec_name, n, sec_name
+            ))
+            .into());
+        }
+    };
+
+    let opt = get_user_confirmation(&format!(
+        "Are you sure you want to restore the value replaced on {} UTC (yes/no)",
+        entry.replaced_at
+    ))?;
+    if !opt {
+        return Ok(());
+    }
+
+    match sec_type {
+        SecretType::Login => {
+            db::change_db_account_field(sec_name, &FieldType::Pass, entry.value.expose(), dbcon)?
+        }
+        SecretType::Api => {
+            db::change_db_apikey_field(sec_name, &FieldType::Key, entry.value.expose(), dbcon)?
+        }
+        SecretType::Ssh => restore_ssh_key(sec_name, &entry.value, dbcon)?,
+        SecretType::Note => {
+            db::change_db_note_field(sec_name, &FieldType::Body, entry.value.expose(), dbcon)?
+        }
+    };
+    println!("Restored Successfully");
+    Ok(())
+}
+
+fn change_tags(
+    sec_type: SecretType,
+    sec_name: &str,
+    args: &ChangeArgs,
+    dbcon: &Connection,
+) -> Result {
+    db::add_tags_to_secret(sec_type, sec_name, &args.add_tags, dbcon)?;
+    db::remove_tags_from_secret(sec_type, sec_name, &args.remove_tags, dbcon)?;
+    println!("Tags Changed Successfully");
+    Ok(())
+}
+
+fn change_custom_field(
+    sec_type: SecretType,
+    sec_name: &str,
+    key: &str,
+    sensitive: bool,
+    dbcon: &Connection,
+) -> Result {
+    // a field stays sensitive once it is, even if --sensitive is not given again.
+    let sensitive = sensitive || db::custom_field_is_sensitive(sec_type, sec_name, key, dbcon)?;
+    let prompt = format!("Enter new value for {} (leave empty to remove it)", key);
+    let value = if sensitive {
+        get_private_input(&prompt, true)?
+    } else {
+        get_terminal_input(&prompt)?.into()
+    };
+
+    if value.is_empty() {
+        let removed = db::remove_custom_field(sec_type, sec_name, key, dbcon)?;
+        if removed == 0 {
+            return Err(
+                CustomError::new(&format!("{} has no custom field {}", sec_name, key)).into(),
+            );
+        }
+        println!("Removed Successfully");
+        return Ok(());
+    }
+
+    let field = CustomField {
+        key: key.to_string(),
+        value,
+        sensitive,
+    };
+    db::set_custom_field(sec_type, sec_name, &field, dbcon)?;
     println!("Changed Successfully");
     Ok(())
 }
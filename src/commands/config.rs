@@ -0,0 +1,30 @@
+use crate::commands::*;
+use crate::util::argparser::{ConfigArgs, ConfigCommands};
+use crate::util::config::CONFIG_KEYS;
+
+pub fn run_config(args: &ConfigArgs, config: &mut Config) -> Result {
+    match &args.command {
+        ConfigCommands::Get(a) => match config.get_key(&a.key)? {
+            Some(value) => println!("{}", value),
+            None => {
+                return Err(CustomError::new(&format!("{} is not set", a.key)).into());
+            }
+        },
+        ConfigCommands::Set(a) => {
+            config.set_key(&a.key, &a.value)?;
+            config.save()?;
+        }
+        ConfigCommands::Unset(a) => {
+            config.unset_key(&a.key)?;
+            config.save()?;
+        }
+        ConfigCommands::List => {
+            for key in CONFIG_KEYS {
+                if let Some(value) = config.get_key(key)? {
+                    println!("{} = {}", key, value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use crate::commands::*;
+use crate::db::cipher::{self, CipherParams};
+use crate::db::migrations;
+use crate::util::argparser::{BenchmarkArgs, CipherAlgorithm, CipherArgs, MigrateArgs, RekdfArgs};
+
+use clap::ValueEnum;
+
+pub fn run_db(args: &DbArgs, dbcon: &Connection) -> Result {
+    match &args.command {
+        DbCommands::Migrate(a) => run_migrate(a, dbcon),
+        DbCommands::Rekdf(a) => run_rekdf(a, dbcon),
+        DbCommands::Benchmark(a) => run_benchmark(a),
+    }
+}
+
+/// Needs no database so it is run before one is opened.
+pub fn run_benchmark(args: &BenchmarkArgs) -> Result {
+    println!(
+        "Timing PBKDF2-HMAC-{} on this machine...",
+        value_name(&args.kdf_algorithm)
+    );
+    let iterations = cipher::benchmark_kdf_iter(args.kdf_algorithm, args.unlock_time)?;
+    println!(
+        "About {} iterations take {} ms to unlock. Use them with cman init --kdf-iter {} or cman db rekdf --kdf-iter {}",
+        iterations, args.unlock_time, iterations, iterations
+    );
+    Ok(())
+}
+
+fn run_rekdf(args: &RekdfArgs, dbcon: &Connection) -> Result {
+    let Some(path) = dbcon.path() else {
+        return Err(CustomError::new("Only a database file can be re-encrypted").into());
+    };
+    let current = CipherParams::load(path)?;
+    let params = cipher_params(&args.cipher, current)?;
+    if params == current {
+        println!("The database already uses these parameters");
+        return Ok(());
+    }
+
+    db::rekdf_db(dbcon, &params)?;
+    println!(
+        "Re-encrypted the database with {} iterations of PBKDF2-HMAC-{}, HMAC-{} and {} byte pages",
+        params.kdf_iter,
+        value_name(&params.kdf_algorithm),
+        value_name(&params.hmac_algorithm),
+        params.page_size
+    );
+    Ok(())
+}
+
+/// The parameters given on the command line, the rest taken from `base`. With --unlock-time the
+/// iterations are benchmarked.
+pub fn cipher_params(
+    args: &CipherArgs,
+    base: CipherParams,
+) -> std::result::Result<CipherParams, CMError> {
+    let mut params = base;
+    if let Some(a) = args.kdf_algorithm {
+        params.kdf_algorithm = a;
+    }
+    if let Some(a) = args.hmac_algorithm {
+        params.hmac_algorithm = a;
+    }
+    if let Some(size) = args.page_size {
+        params.page_size = size;
+    }
+    if let Some(n) = args.kdf_iter {
+        params.kdf_iter = n;
+    }
+    if let Some(ms) = args.unlock_time {
+        params.kdf_iter = cipher::benchmark_kdf_iter(params.kdf_algorithm, ms)?;
+        println!(
+            "Using {} iterations to unlock in about {} ms",
+            params.kdf_iter, ms
+        );
+    }
+    Ok(params)
+}
+
+fn value_name(algorithm: &CipherAlgorithm) -> String {
+    algorithm
+        .to_possible_value()
+        .map(|v| v.get_name().to_uppercase())
+        .unwrap_or_default()
+}
+
+fn run_migrate(args: &MigrateArgs, dbcon: &Connection) -> Result {
+    let current = migrations::get_schema_version(dbcon)?;
+    println!(
+        "Schema version: {} (latest: {})",
+        current,
+        migrations::latest_version()
+    );
+
+    if args.dry_run {
+        let pending = migrations::get_pending_migrations(dbcon)?;
+        if pending.is_empty() {
+            println!("Database schema is up to date");
+            return Ok(());
+        }
+        println!("\nPending migrations:");
+        for migration in pending {
+            println!("{:>4}: {}", migration.version, migration.description);
+        }
+        return Ok(());
+    }
+
+    let applied = migrations::run_migrations(dbcon)?;
+    if applied.is_empty() {
+        println!("Database schema is up to date");
+        return Ok(());
+    }
+    println!("\nApplied migrations:");
+    for migration in applied {
+        println!("{:>4}: {}", migration.version, migration.description);
+    }
+    Ok(())
+}
@@ -1,112 +1,79 @@
 use crate::commands::*;
 
 pub fn run_delete(args: &DeleteArgs, dbcon: &Connection) -> Result {
-    let secret_type = args.secret_type.unwrap_or(SecretType::Login);
+    let secret_type = secret_type_or_default(args.secret_type);
 
-    match secret_type {
-        SecretType::Login => delete_acc(args, dbcon)?,
-        SecretType::Api => delete_api(args, dbcon)?,
-    };
-    Ok(())
-}
-
-fn delete_acc(args: &DeleteArgs, dbcon: &Connection) -> Result {
-    let accounts = match &args.secret {
-        Some(accounts) => accounts.clone(),
+    let secrets = match &args.secret {
+        Some(secrets) => secrets.clone(),
         None => {
             if args.multiple {
-                let selections: Vec<String> = get::get_multiple_accounts_from_user(dbcon)?
+                get::get_multiple_secrets_from_user(secret_type, dbcon, &[])?
                     .iter()
                     .map(|secret| secret.get_name())
-                    .collect();
-                selections
+                    .collect()
             } else {
-                let acc_obj = get_account_from_user(dbcon)?;
-                if let Secret::Account(acc) = acc_obj {
-                    vec![acc.account_name.clone()]
-                } else {
-                    vec!["".to_string()]
-                }
+                vec![get_secret_from_user(secret_type, dbcon, &[])?.get_name()]
             }
         }
     };
 
     let mut error_str = String::new();
     let mut successfull: Vec<String> = Vec::new();
-    for account in accounts {
-        let exists = db::check_account_exists(&account, dbcon)?;
-        if !exists {
-            error_str.push_str(&format!("Account {} does not exist\n", account));
-            continue;
-        }
-        let opt = get_user_confirmation(&format!(
-            "Are you sure you want to delete {} (yes/no)",
-            account
-        ))?;
-        if !opt {
-            continue;
-        }
-        db::delete_account_from_db(&account, dbcon)?;
-        successfull.push(account)
-    }
-
-    if !successfull.is_empty() {
-        println!("\nSuccessfully deleted:");
-        for name in successfull {
-            print!("{} ", name);
-        }
-        println!();
-    }
-    if !error_str.is_empty() {
-        return Err(CustomError::new(&error_str).into());
-    }
-    Ok(())
-}
-
-fn delete_api(args: &DeleteArgs, dbcon: &Connection) -> Result {
-    let apikeys = match &args.secret {
-        Some(apikeys) => apikeys.clone(),
-        None => {
-            if args.multiple {
-                get::get_multiple_apikeys_from_user(dbcon)?
-                    .iter()
-                    .map(|secret| secret.get_name())
-                    .collect()
-            } else {
-                let api_obj = get_api_from_user(dbcon)?;
-                if let Secret::API(api) = api_obj {
-                    vec![api.api_name.clone()]
-                } else {
-                    vec!["".to_string()]
+    for secret in secrets {
+        // names typed on the command line are looked up across all types, names picked from the
+        // menu already belong to secret_type.
+        let (secret_type, secret) = match &args.secret {
+            Some(_) => match resolve_secret(&secret, args.secret_type, dbcon) {
+                Ok(resolved) => resolved,
+                Err(CMError::Custom(e)) => {
+                    error_str.push_str(&format!("{}\n", e));
+                    continue;
                 }
-            }
-        }
-    };
-    let mut error_str = String::new();
-    let mut successfull: Vec<String> = Vec::new();
-    for apikey in apikeys {
-        let exists = db::check_apikey_exists(&apikey, dbcon)?;
+                Err(e) => return Err(e),
+            },
+            None => (secret_type, secret),
+        };
+        let (type_name, _) = type_names(secret_type);
+        let exists = db::check_secret_exists(secret_type, &secret, dbcon)?;
         if !exists {
-            error_str.push_str(&format!("API Key {} does not exist\n", apikey));
+            error_str.push_str(&format!("{} {} does not exist\n", type_name, secret));
             continue;
         }
-        let opt = get_user_confirmation(&format!(
-            "Are you sure you want to delete {} (yes/no)",
-            apikey
-        ))?;
-        if !opt {
-            continue;
+        if args.permanent {
+            let opt = get_user_confirmation(&format!(
+                "Are you sure you want to permanently delete {} (yes/no)",
+                secret
+            ))?;
+            if !opt {
+                continue;
+            }
+            db::delete_secret_from_db(secret_type, &secret, dbcon)?;
+        } else {
+            let opt = get_user_confirmation(&format!(
+                "Are you sure you want to move {} to the trash (yes/no)",
+                secret
+            ))?;
+            if !opt {
+                continue;
+            }
+            db::trash_secret(secret_type, &secret, dbcon)?;
         }
-        db::delete_apikey_from_db(&apikey, dbcon)?;
-        successfull.push(apikey)
+        successfull.push(secret)
     }
 
     if !successfull.is_empty() {
-        println!("\nSuccessfully deleted:");
+        if args.permanent {
+            println!("\nSuccessfully deleted:");
+        } else {
+            println!("\nMoved to the trash:");
+        }
         for name in successfull {
             print!("{} ", name);
         }
         println!();
+        if !args.permanent {
+            println!("Use cman restore <name> to bring a secret back.");
+        }
     }
     if !error_str.is_empty() {
         return Err(CustomError::new(&error_str).into());
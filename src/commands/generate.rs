@@ -0,0 +1,51 @@
+use crate::commands::*;
+use crate::util::argparser::GenerateArgs;
+use crate::util::clipboard::copy_to_clipboard;
+use crate::util::passgen::Generator;
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Generated {
+    kind: &'static str,
+    entropy: f64,
+    values: Vec<SecretString>,
+}
+
+pub fn run_generate(args: &GenerateArgs) -> Result {
+    let generator = Generator::from_settings(args.passlen)?;
+    let kind = match generator {
+        Generator::Password(_) => "password",
+        Generator::Passphrase(_) => "passphrase",
+    };
+    let values = (0..args.count)
+        .map(|_| generator.generate())
+        .collect::<std::result::Result<Vec<SecretString>, CMError>>()?;
+    // rounded down so that the estimate is never more than the policy gives.
+    let entropy = (generator.entropy() * 10.0).floor() / 10.0;
+
+    let generated = Generated {
+        kind,
+        entropy,
+        values,
+    };
+
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&generated).unwrap_or("".to_string());
+        println!("{}", json_str);
+    } else {
+        for value in &generated.values {
+            println!("{}", value.expose());
+        }
+        if !args.quiet {
+            println!("About {:.0} bits of entropy", entropy.floor());
+        }
+    }
+
+    if args.clip
+        && let Some(value) = generated.values.first()
+    {
+        copy_to_clipboard(value.expose())?;
+    }
+    Ok(())
+}
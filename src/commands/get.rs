@@ -2,43 +2,56 @@ use crate::{commands::*, util::ioutils};
 use serde_json;
 
 pub fn run_get(args: &GetArgs, dbcon: &Connection) -> Result {
-    let sec_type = args.secret_type.unwrap_or(SecretType::Login);
+    let sec_type = secret_type_or_default(args.secret_type);
     let secrets = match &args.secret {
-        Some(s) => match sec_type {
-            SecretType::Login => get_accounts(s, dbcon)?,
-            SecretType::Api => get_apikeys(s, dbcon)?,
-        },
-        None => match sec_type {
-            SecretType::Login => {
-                if args.multiple {
-                    get_multiple_accounts_from_user(dbcon)?
-                } else {
-                    vec![get_account_from_user(dbcon)?]
-                }
+        Some(s) => get_secrets(args.secret_type, s, dbcon)?,
+        None => {
+            if args.multiple {
+                get_multiple_secrets_from_user(sec_type, dbcon, &args.tags)?
+            } else {
+                vec![get_secret_from_user(sec_type, dbcon, &args.tags)?]
             }
-            SecretType::Api => {
-                if args.multiple {
-                    get_multiple_apikeys_from_user(dbcon)?
-                } else {
-                    vec![get_api_from_user(dbcon)?]
-                }
-            }
-        },
+        }
     };
 
     if secrets.is_empty() {
         return Ok(());
     }
 
+    let logged_field = match (&args.attachment, &args.field) {
+        (Some(file_name), _) => Some(format!("attachment:{}", file_name)),
+        (None, Some(fieldtype)) => Some(fieldtype.name()),
+        (None, None) => None,
+    };
+    for secret in &secrets {
+        db::mark_secret_accessed(secret, dbcon)?;
+        db::log_operation(
+            AuditOperation::Get,
+            Some(secret.get_type()),
+            Some(&secret.get_name()),
+            logged_field.as_deref(),
+            dbcon,
+        )?;
+    }
+
+    if let Some(file_name) = &args.attachment {
+        if secrets.len() != 1 {
+            return Err(
+                CustomError::new("An attachment can only be extracted from one secret").into(),
+            );
+        }
+        return attach::extract_attachment(&secrets[0], file_name, &args.out, dbcon);
+    }
+
     // if user requires json we combine everything in a single json object.
-    if args.json {
-        if let Some(fieldtype) = args.field {
+    if json_output(args.json) {
+        if let Some(fieldtype) = &args.field {
             if secrets.len() == 1 {
                 println!("{}", secrets[0].get_field_json_str(fieldtype));
                 return Ok(());
             }
 
-            let secrets_fields: Vec<String> =
+            let secrets_fields: Vec<SecretString> =
                 secrets.iter().map(|s| s.get_field(fieldtype)).collect();
             let json = match serde_json::to_string_pretty(&secrets_fields) {
                 Err(_) => "".to_string(),
@@ -64,17 +77,20 @@ pub fn run_get(args: &GetArgs, dbcon: &Connection) -> Result {
     Ok(())
 }
 
-fn get_accounts(
-    accounts: &Vec<String>,
+/// Looks up each name across all secret types unless a type is given.
+fn get_secrets(
+    sec_type: Option<SecretType>,
+    names: &Vec<String>,
     dbcon: &Connection,
 ) -> core::result::Result<Vec<Secret>, CMError> {
-    let mut account_objs: Vec<Secret> = Vec::new();
+    let mut secret_objs: Vec<Secret> = Vec::new();
     let mut errors: Vec<CMError> = Vec::new();
 
-    for account in accounts {
-        let account = db::get_account_from_db(account, dbcon);
-        match account {
-            Ok(a) => account_objs.push(a),
+    for name in names {
+        let secret = resolve_secret(name, sec_type, dbcon)
+            .and_then(|(t, name)| db::get_secret_from_db(t, &name, dbcon));
+        match secret {
+            Ok(s) => secret_objs.push(s),
             Err(e) => errors.push(e),
         }
     }
@@ -84,73 +100,54 @@ fn get_accounts(
         }
         eprintln!();
     }
-    Ok(account_objs)
+    Ok(secret_objs)
 }
 
-fn get_apikeys(
-    apikeys: &Vec<String>,
+/// Offers the stored secrets of a type in an interactive menu. If tags are given only secrets
+/// with all of them are offered.
+pub fn get_secret_from_user(
+    sec_type: SecretType,
     dbcon: &Connection,
-) -> core::result::Result<Vec<Secret>, CMError> {
-    let mut api_objs: Vec<Secret> = Vec::new();
-    let mut errors: Vec<CMError> = Vec::new();
-
-    for apikey in apikeys {
-        let apikey = db::get_apikey_from_db(apikey, dbcon);
-        match apikey {
-            Ok(api) => api_objs.push(api),
-            Err(e) => errors.push(e),
-        }
-    }
-    if !errors.is_empty() {
-        for e in errors {
-            eprintln!("{}", e);
-        }
-        eprintln!();
-    }
-    Ok(api_objs)
-}
-
-pub fn get_account_from_user(dbcon: &Connection) -> core::result::Result<Secret, CMError> {
-    let all_accounts = db::get_all_accounts_from_db(dbcon)?;
-    if all_accounts.is_empty() {
-        return Err(CustomError::new(
-            "No accounts added yet. Use cman add <account_name> to add your first account. See cman add --help for more details.",
-        ).into());
-    }
-    get_terminal_input_with_suggestions("Enter the account name", all_accounts)
+    tags: &[String],
+) -> core::result::Result<Secret, CMError> {
+    let all_secrets = get_all_secrets_with_tags(sec_type, dbcon, tags)?;
+    let (singular, _) = type_names(sec_type);
+    get_terminal_input_with_suggestions(
+        &format!("Enter the {} name", singular.to_lowercase()),
+        all_secrets,
+    )
 }
 
-pub fn get_api_from_user(dbcon: &Connection) -> core::result::Result<Secret, CMError> {
-    let all_api_keys = db::get_all_apikeys_from_db(dbcon)?;
-    if all_api_keys.is_empty() {
-        return Err(CustomError::new(
-            "No api keys added yet. Use cman add <api_name> to add your first api key. See cman add --help for more details.",
-        ).into());
-    }
-    get_terminal_input_with_suggestions("Enter the api key name", all_api_keys)
-}
-
-pub fn get_multiple_accounts_from_user(
+pub fn get_multiple_secrets_from_user(
+    sec_type: SecretType,
     dbcon: &Connection,
+    tags: &[String],
 ) -> core::result::Result<Vec<Secret>, CMError> {
-    let all_accounts = db::get_all_accounts_from_db(dbcon)?;
-    if all_accounts.is_empty() {
-        return Err(CustomError::new(
-            "No accounts added yet. Use cman add <account_name> to add your first account. See cman add --help for more details.",
-        ).into());
-    }
-
-    get_multiple_selections_from_terminal("Select accounts", all_accounts)
+    let all_secrets = get_all_secrets_with_tags(sec_type, dbcon, tags)?;
+    let (_, plural) = type_names(sec_type);
+    get_multiple_selections_from_terminal(&format!("Select {}", plural), all_secrets)
 }
 
-pub fn get_multiple_apikeys_from_user(
+fn get_all_secrets_with_tags(
+    sec_type: SecretType,
     dbcon: &Connection,
+    tags: &[String],
 ) -> core::result::Result<Vec<Secret>, CMError> {
-    let all_api_keys = db::get_all_apikeys_from_db(dbcon)?;
-    if all_api_keys.is_empty() {
-        return Err(CustomError::new(
-            "No api keys added yet. Use cman add <api_name> to add your first api key. See cman add --help for more details.",
-        ).into());
+    let (singular, plural) = type_names(sec_type);
+    let mut all_secrets = db::get_all_secrets_from_db(sec_type, dbcon)?;
+    if all_secrets.is_empty() {
+        return Err(CustomError::new(&format!(
+            "No {} added yet. Use cman add <name> --type {} to add your first {}. See cman add --help for more details.",
+            plural,
+            type_flag(sec_type),
+            singular.to_lowercase()
+        ))
+        .into());
+    }
+
+    retain_tagged(&mut all_secrets, tags);
+    if all_secrets.is_empty() {
+        return Err(CustomError::new(&format!("No {} tagged {}", plural, tags.join(", "))).into());
     }
-    get_multiple_selections_from_terminal("Select API Keys", all_api_keys)
+    Ok(all_secrets)
 }
@@ -0,0 +1,49 @@
+use crate::commands::*;
+use crate::util::argparser::HistoryArgs;
+
+pub fn run_history(args: &HistoryArgs, dbcon: &Connection) -> Result {
+    let (sec_type, sec_name) = match &args.secret {
+        Some(s) => resolve_secret(s, args.secret_type, dbcon)?,
+        None => {
+            let sec_type = secret_type_or_default(args.secret_type);
+            (
+                sec_type,
+                get_secret_from_user(sec_type, dbcon, &[])?.get_name(),
+            )
+        }
+    };
+
+    let exists = db::check_secret_exists(sec_type, &sec_name, dbcon)?;
+    if !exists {
+        let (type_name, _) = type_names(sec_type);
+        return Err(CustomError::new(&format!("{} {} does not exist", type_name, sec_name)).into());
+    }
+
+    let history = db::get_secret_history(sec_type, &sec_name, dbcon)?;
+    db::log_operation(
+        AuditOperation::Get,
+        Some(sec_type),
+        Some(&sec_name),
+        Some("history"),
+        dbcon,
+    )?;
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&history).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if history.is_empty() {
+        println!("No previous values recorded for {}", sec_name);
+        return Ok(());
+    }
+    for (i, entry) in history.iter().enumerate() {
+        println!(
+            "{:>3}  {} UTC  {}",
+            i + 1,
+            entry.replaced_at,
+            entry.value.expose().trim_end()
+        );
+    }
+    Ok(())
+}
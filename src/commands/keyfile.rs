@@ -0,0 +1,15 @@
+use crate::commands::*;
+use crate::util::argparser::{KeyfileArgs, KeyfileCommands};
+
+pub fn run_keyfile(args: &KeyfileArgs) -> Result {
+    match &args.command {
+        KeyfileCommands::New(a) => {
+            key::create_keyfile(Path::new(&a.path))?;
+            println!(
+                "Keyfile written to {}. Keep a backup of it somewhere safe",
+                a.path
+            );
+        }
+    }
+    Ok(())
+}
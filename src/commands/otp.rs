@@ -0,0 +1,52 @@
+use crate::commands::*;
+use crate::util::clipboard::copy_to_clipboard;
+
+pub fn run_otp(args: &OtpArgs, dbcon: &Connection) -> Result {
+    let secret = match &args.secret {
+        Some(name) => db::get_account_from_db(name, dbcon)?,
+        None => get_secret_from_user(SecretType::Login, dbcon, &[])?,
+    };
+    let sec_name = secret.get_name();
+
+    let uri = secret.get_field(&FieldType::Otp);
+    if uri.is_empty() {
+        return Err(CustomError::new(&format!(
+            "Account {} has no otp seed. Set one with cman change {} --field otp",
+            sec_name, sec_name
+        ))
+        .into());
+    }
+    let mut otp_secret = OtpSecret::parse(uri.expose())?;
+    let (code, remaining) = otp_secret.generate_now();
+
+    // every hotp code can only be used once so the counter moves on as soon as one is shown.
+    if let OtpKind::Hotp { counter } = otp_secret.kind {
+        otp_secret.kind = OtpKind::Hotp {
+            counter: counter + 1,
+        };
+        db::change_db_account_field(
+            &sec_name,
+            &FieldType::Otp,
+            otp_secret.to_uri().expose(),
+            dbcon,
+        )?;
+    }
+    db::mark_secret_accessed(&secret, dbcon)?;
+    db::log_operation(
+        AuditOperation::Get,
+        Some(SecretType::Login),
+        Some(&sec_name),
+        Some("otp"),
+        dbcon,
+    )?;
+
+    ioutils::print_result("Code", &code);
+    if let Some(seconds) = remaining
+        && !args.quiet
+    {
+        println!("Valid for {} more seconds", seconds);
+    }
+
+    copy_to_clipboard(&code)?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::commands::*;
+use crate::objects::SearchResult;
+use crate::util::argparser::SearchArgs;
+
+use clap::ValueEnum;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+/// A match on the name of a secret counts this many times as much as a match on another field.
+const NAME_WEIGHT: i64 = 2;
+
+pub fn run_search(args: &SearchArgs, dbcon: &Connection) -> Result {
+    if args.query.trim().is_empty() {
+        return Err(CustomError::new("The search query cannot be empty").into());
+    }
+
+    let mut results = if args.fts {
+        fts_search(&args.query, args.secret_type, dbcon)?
+    } else {
+        fuzzy_search(&args.query, args.secret_type, dbcon)?
+    };
+    if let Some(limit) = args.limit {
+        results.truncate(limit);
+    }
+
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&results).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        println!("No secrets match {}", args.query);
+        return Ok(());
+    }
+    for result in results {
+        if result.field == "secname" {
+            println!("{:<6} {}", result.secret_type, result.name);
+            continue;
+        }
+        // multi-line values such as notes are shortened to their first line.
+        let value = result.value.expose().lines().next().unwrap_or_default();
+        println!(
+            "{:<6} {}  ({}: {})",
+            result.secret_type, result.name, result.field, value
+        );
+    }
+    Ok(())
+}
+
+fn fuzzy_search(
+    query: &str,
+    sec_type: Option<SecretType>,
+    dbcon: &Connection,
+) -> std::result::Result<Vec<SearchResult>, CMError> {
+    let types = match sec_type {
+        Some(t) => vec![t],
+        None => SecretType::value_variants().to_vec(),
+    };
+
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut results = Vec::new();
+    for t in types {
+        for secret in db::get_all_secrets_from_db(t, dbcon)? {
+            if let Some(result) = best_match(&secret, query, &matcher) {
+                results.push(result);
+            }
+        }
+    }
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    Ok(results)
+}
+
+/// Searches the full text index, rebuilding it first if the vault has changed since it was built.
+fn fts_search(
+    query: &str,
+    sec_type: Option<SecretType>,
+    dbcon: &Connection,
+) -> std::result::Result<Vec<SearchResult>, CMError> {
+    if db::search_index_is_stale(dbcon)? {
+        let mut secrets = Vec::new();
+        for t in SecretType::value_variants() {
+            secrets.extend(db::get_all_secrets_from_db(*t, dbcon)?);
+        }
+        db::rebuild_search_index(&secrets, dbcon)?;
+    }
+
+    let found = db::search_index(query, sec_type, dbcon)?;
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut results = Vec::new();
+    // the index already ranks the results so the score only keeps that order.
+    for (rank, (t, name)) in found.iter().enumerate() {
+        let secret = db::get_secret_from_db(*t, name, dbcon)?;
+        let score = (found.len() - rank) as i64;
+        let result = match best_match(&secret, query, &matcher) {
+            Some(result) => SearchResult { score, ..result },
+            None => SearchResult {
+                secret_type: type_flag(*t).to_string(),
+                name: name.clone(),
+                field: "secname".to_string(),
+                value: name.as_str().into(),
+                score,
+            },
+        };
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// The best scoring field of a secret, or None if no field matches the query.
+fn best_match(secret: &Secret, query: &str, matcher: &SkimMatcherV2) -> Option<SearchResult> {
+    let mut best: Option<(i64, String, SecretString)> = None;
+    for (field, value) in secret.search_fields() {
+        let Some(mut score) = matcher.fuzzy_match(value.expose(), query) else {
+            continue;
+        };
+        if field == "secname" {
+            score *= NAME_WEIGHT;
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _, _)| score > *best_score)
+        {
+            best = Some((score, field, value));
+        }
+    }
+
+    best.map(|(score, field, value)| SearchResult {
+        secret_type: type_flag(secret.get_type()).to_string(),
+        name: secret.get_name(),
+        field,
+        value,
+        score,
+    })
+}
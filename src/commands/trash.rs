@@ -0,0 +1,113 @@
+use crate::commands::*;
+use crate::objects::TrashEntry;
+use crate::util::argparser::{PurgeArgs, RestoreArgs, TrashArgs, TrashCommands, TrashLsArgs};
+
+use clap::ValueEnum;
+
+pub fn run_trash(args: &TrashArgs, dbcon: &Connection) -> Result {
+    match &args.command {
+        TrashCommands::Ls(a) => run_trash_ls(a, dbcon),
+        TrashCommands::Purge(a) => run_purge(a, dbcon),
+    }
+}
+
+pub fn run_restore(args: &RestoreArgs, dbcon: &Connection) -> Result {
+    let sec_type = secret_type_or_default(args.secret_type);
+    let (type_name, type_plural) = type_names(sec_type);
+
+    let sec_name = match &args.secret {
+        Some(s) => s.clone(),
+        None => {
+            let trashed: Vec<String> = db::get_trashed_secrets(sec_type, None, dbcon)?
+                .into_iter()
+                .map(|e| e.name)
+                .collect();
+            if trashed.is_empty() {
+                return Err(CustomError::new(&format!(
+                    "There are no {} in the trash",
+                    type_plural
+                ))
+                .into());
+            }
+            get_terminal_input_with_suggestions("Choose a secret to restore", trashed)?
+        }
+    };
+
+    let restored = db::restore_secret_from_trash(sec_type, &sec_name, dbcon)?;
+    if restored == 0 {
+        return Err(
+            CustomError::new(&format!("{} {} is not in the trash", type_name, sec_name)).into(),
+        );
+    }
+    println!("Restored Successfully");
+    Ok(())
+}
+
+fn run_trash_ls(args: &TrashLsArgs, dbcon: &Connection) -> Result {
+    let entries: Vec<TrashEntry> = trashed_secrets(args.secret_type, None, dbcon)?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect();
+
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&entries).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+    for entry in entries {
+        println!(
+            "{:<6} {}  trashed {} UTC",
+            entry.secret_type, entry.name, entry.deleted_at
+        );
+    }
+    Ok(())
+}
+
+fn run_purge(args: &PurgeArgs, dbcon: &Connection) -> Result {
+    let entries = trashed_secrets(args.secret_type, args.older_than, dbcon)?;
+    if entries.is_empty() {
+        println!("Nothing to purge");
+        return Ok(());
+    }
+
+    if !args.yes {
+        let opt = get_user_confirmation(&format!(
+            "Are you sure you want to permanently delete {} secret(s) from the trash (yes/no)",
+            entries.len()
+        ))?;
+        if !opt {
+            return Ok(());
+        }
+    }
+
+    for (sec_type, entry) in &entries {
+        db::delete_secret_from_db(*sec_type, &entry.name, dbcon)?;
+    }
+    println!("Purged {} secret(s) from the trash", entries.len());
+    Ok(())
+}
+
+/// The trashed secrets of one type, or of all types if none is given.
+fn trashed_secrets(
+    sec_type: Option<SecretType>,
+    older_than: Option<u64>,
+    dbcon: &Connection,
+) -> std::result::Result<Vec<(SecretType, TrashEntry)>, CMError> {
+    let types = match sec_type {
+        Some(t) => vec![t],
+        None => SecretType::value_variants().to_vec(),
+    };
+
+    let mut entries = Vec::new();
+    for t in types {
+        for entry in db::get_trashed_secrets(t, older_than, dbcon)? {
+            entries.push((t, entry));
+        }
+    }
+    Ok(entries)
+}
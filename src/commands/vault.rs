@@ -0,0 +1,134 @@
+use crate::commands::*;
+use crate::objects::VaultEntry;
+use crate::util::argparser::{
+    VaultAddArgs, VaultArgs, VaultCommands, VaultDefaultArgs, VaultLsArgs, VaultRemoveArgs,
+};
+
+use std::path::Path;
+
+pub fn run_vault(args: &VaultArgs, config: &mut Config) -> Result {
+    match &args.command {
+        VaultCommands::Ls(a) => run_vault_ls(a, config),
+        VaultCommands::Add(a) => run_vault_add(a, config),
+        VaultCommands::Remove(a) => run_vault_remove(a, config),
+        VaultCommands::Default(a) => run_vault_default(a, config),
+    }
+}
+
+fn run_vault_ls(args: &VaultLsArgs, config: &Config) -> Result {
+    let entries: Vec<VaultEntry> = config
+        .vaults
+        .iter()
+        .map(|(name, vault)| VaultEntry {
+            name: name.clone(),
+            path: vault.path.clone(),
+            url: vault.url.clone(),
+            keyfile: vault.keyfile.clone(),
+            default: config.default_vault.as_ref() == Some(name),
+        })
+        .collect();
+
+    if json_output(args.json) {
+        let json_str = serde_json::to_string_pretty(&entries).unwrap_or("".to_string());
+        println!("{}", json_str);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No vaults are registered. Add one with cman vault add <name> <path>");
+        return Ok(());
+    }
+    for entry in entries {
+        let marker = if entry.default { "*" } else { " " };
+        match &entry.url {
+            Some(url) => println!("{} {:<12} {}  ({})", marker, entry.name, entry.path, url),
+            None => println!("{} {:<12} {}", marker, entry.name, entry.path),
+        }
+    }
+    Ok(())
+}
+
+fn run_vault_add(args: &VaultAddArgs, config: &mut Config) -> Result {
+    if args.name.is_empty() {
+        return Err(CustomError::new("The vault name cannot be empty").into());
+    }
+    if let Some(vault) = config.vaults.get(&args.name) {
+        return Err(CustomError::new(&format!(
+            "Vault {} is already registered at {}. Remove it first with cman vault remove {}",
+            args.name, vault.path, args.name
+        ))
+        .into());
+    }
+
+    let path = absolute_path(&args.path)?;
+    register_vault(&args.name, &path, args.url.clone(), config);
+    if let Some(keyfile) = &args.keyfile {
+        key::check_keyfile(Path::new(keyfile))?;
+        if let Some(vault) = config.vaults.get_mut(&args.name) {
+            vault.keyfile = Some(absolute_path(keyfile)?);
+        }
+    }
+    if args.default {
+        config.default_vault = Some(args.name.clone());
+    }
+    config.save()?;
+
+    println!("Registered vault {} at {}", args.name, path);
+    if !Path::new(&path).exists() {
+        println!(
+            "The database does not exist yet. Create it with cman --vault {} init",
+            args.name
+        );
+    }
+    Ok(())
+}
+
+fn run_vault_remove(args: &VaultRemoveArgs, config: &mut Config) -> Result {
+    let vault = config.get_vault(&args.name)?.clone();
+    config.vaults.remove(&args.name);
+    if config.default_vault.as_ref() == Some(&args.name) {
+        config.default_vault = None;
+    }
+    config.save()?;
+
+    println!(
+        "Removed vault {}. The database at {} was left in place",
+        args.name, vault.path
+    );
+    Ok(())
+}
+
+fn run_vault_default(args: &VaultDefaultArgs, config: &mut Config) -> Result {
+    match &args.name {
+        Some(name) => {
+            config.get_vault(name)?;
+            config.default_vault = Some(name.clone());
+            config.save()?;
+            println!("{} is now the default vault", name);
+        }
+        None => {
+            config.default_vault = None;
+            config.save()?;
+            println!("The default vault has been unset");
+        }
+    }
+    Ok(())
+}
+
+/// Adds a vault to the registry without saving the config file.
+pub fn register_vault(name: &str, path: &str, url: Option<String>, config: &mut Config) {
+    config.vaults.insert(
+        name.to_string(),
+        Vault {
+            path: path.to_string(),
+            url,
+            keyfile: None,
+        },
+    );
+}
+
+/// Vault paths are stored absolute so that they work from any directory.
+pub fn absolute_path(path: &str) -> std::result::Result<String, CMError> {
+    let path = std::path::absolute(path)?;
+    Ok(path.to_string_lossy().to_string())
+}
@@ -1,8 +1,14 @@
+pub mod cipher;
 pub mod general;
+pub mod key;
+pub mod migrations;
 pub mod operations;
 
 pub use general::change_db_password;
 pub use general::create_new_db;
 pub use general::get_db_con;
+pub use general::open_db_con;
+pub use general::open_db_con_without_prompt;
+pub use general::rekdf_db;
 
 pub use operations::*;
@@ -0,0 +1,234 @@
+use crate::util::argparser::CipherAlgorithm;
+use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
+
+use rand::TryRngCore;
+use rand::rngs::OsRng;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// The iterations SQLCipher 4 uses when a database does not say otherwise.
+pub const DEFAULT_KDF_ITER: u32 = 256000;
+pub const DEFAULT_PAGE_SIZE: u32 = 4096;
+/// The smallest iteration count a benchmark suggests however slow the machine is.
+const MIN_KDF_ITER: u32 = 10000;
+/// The iterations the benchmark times before scaling them to the target unlock time.
+const BENCHMARK_KDF_ITER: u32 = 100000;
+/// SQLCipher keeps a random salt in the first bytes of the database file.
+pub const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+pub type Salt = [u8; SALT_LEN];
+
+/// How SQLCipher derives the encryption key from the master password and checks pages. The
+/// parameters cannot be read from an encrypted database, so they are kept in a plaintext file next
+/// to it. A database without that file uses the SQLCipher 4 defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CipherParams {
+    pub kdf_iter: u32,
+    pub kdf_algorithm: CipherAlgorithm,
+    pub hmac_algorithm: CipherAlgorithm,
+    pub page_size: u32,
+}
+
+impl Default for CipherParams {
+    fn default() -> Self {
+        Self {
+            kdf_iter: DEFAULT_KDF_ITER,
+            kdf_algorithm: CipherAlgorithm::Sha512,
+            hmac_algorithm: CipherAlgorithm::Sha512,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl CipherParams {
+    /// Reads the parameters of the database at dbfile, or the defaults if it has none recorded.
+    pub fn load(dbfile: &str) -> Result<Self, CMError> {
+        Ok(Self::read(&params_path(dbfile))?.unwrap_or_default())
+    }
+
+    /// Writes the parameters of the database at dbfile. The file is replaced in one step, so it
+    /// always holds either the old or the new parameters.
+    pub fn save(&self, dbfile: &str) -> Result<(), CMError> {
+        self.write(&params_path(dbfile))
+    }
+
+    /// The parameters the database had before cman db rekdf started to re-encrypt it. They are
+    /// only kept while it runs, so that a database left behind by an interrupted run still opens.
+    pub fn load_previous(dbfile: &str) -> Result<Option<Self>, CMError> {
+        Self::read(&previous_params_path(dbfile))
+    }
+
+    pub fn save_previous(&self, dbfile: &str) -> Result<(), CMError> {
+        self.write(&previous_params_path(dbfile))
+    }
+
+    pub fn remove_previous(dbfile: &str) -> Result<(), CMError> {
+        match std::fs::remove_file(previous_params_path(dbfile)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the recorded parameters of the database at dbfile, which then uses the defaults.
+    pub fn remove(dbfile: &str) -> Result<(), CMError> {
+        for path in [params_path(dbfile), previous_params_path(dbfile)] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses parameters in the format of the .cipher file. `origin` names where they came from
+    /// in the error.
+    pub fn parse(contents: &str, origin: &str) -> Result<Self, CMError> {
+        serde_json::from_str(contents).map_err(|e| {
+            CustomError::new(&format!(
+                "Could not read the cipher parameters in {}: {}",
+                origin, e
+            ))
+            .into()
+        })
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>, CMError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Self::parse(&contents, &path.display().to_string()).map(Some)
+    }
+
+    fn write(&self, path: &Path) -> Result<(), CMError> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| {
+            CMError::from(CustomError::new(&format!(
+                "Could not write the cipher parameters: {}",
+                e
+            )))
+        })?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, contents + "\n")?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Sets the parameters on the database `schema` of the connection. This has to happen after
+    /// its key is given and before anything is read from it. cman derives the encryption key
+    /// itself, but SQLCipher still derives the HMAC key from it with the KDF algorithm.
+    pub fn apply(&self, schema: &str, dbcon: &Connection) -> Result<(), CMError> {
+        let query = format!(
+            "PRAGMA {schema}.cipher_page_size = {};\
+            PRAGMA {schema}.cipher_kdf_algorithm = PBKDF2_HMAC_{};\
+            PRAGMA {schema}.cipher_hmac_algorithm = HMAC_{};",
+            self.page_size,
+            self.kdf_algorithm.pragma_name(),
+            self.hmac_algorithm.pragma_name(),
+        );
+        dbcon.execute_batch(&query)?;
+        Ok(())
+    }
+
+    /// Derives the raw encryption key from a passphrase the same way SQLCipher does, so that
+    /// databases keyed with the passphrase itself by older versions of cman still open.
+    pub fn derive_key(&self, passphrase: &str, salt: &Salt) -> Zeroizing<[u8; KEY_LEN]> {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        let password = passphrase.as_bytes();
+        match self.kdf_algorithm {
+            CipherAlgorithm::Sha1 => {
+                pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, self.kdf_iter, key.as_mut())
+            }
+            CipherAlgorithm::Sha256 => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, self.kdf_iter, key.as_mut())
+            }
+            CipherAlgorithm::Sha512 => {
+                pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, self.kdf_iter, key.as_mut())
+            }
+        }
+        key
+    }
+}
+
+/// The value of PRAGMA key for a raw key, x'<key>' in hex. With a salt it is appended, which is
+/// how the salt of a new database is chosen.
+pub fn raw_key_value(key: &[u8; KEY_LEN], salt: Option<&Salt>) -> SecretString {
+    // allocated once so that growing it leaves no copies of the key behind.
+    let mut value = String::with_capacity(2 * (KEY_LEN + SALT_LEN) + 3);
+    value.push_str("x'");
+    for b in key.iter().chain(salt.into_iter().flatten()) {
+        value.push_str(&format!("{:02x}", b));
+    }
+    value.push('\'');
+    value.into()
+}
+
+/// The salt at the start of an existing database file.
+pub fn read_salt(dbfile: &str) -> Result<Salt, CMError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut file = std::fs::File::open(dbfile)?;
+    file.read_exact(&mut salt).map_err(|_| {
+        CMError::from(CustomError::new(&format!(
+            "{} is not a cman database",
+            dbfile
+        )))
+    })?;
+    Ok(salt)
+}
+
+pub fn new_salt() -> Result<Salt, CMError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng
+        .try_fill_bytes(&mut salt)
+        .map_err(|_| CustomError::new("Could not get random bytes for the database salt"))?;
+    Ok(salt)
+}
+
+impl CipherAlgorithm {
+    fn pragma_name(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// The plaintext file the parameters of a database are kept in, the database path with .cipher
+/// appended.
+pub fn params_path(dbfile: &str) -> PathBuf {
+    PathBuf::from(format!("{}.cipher", dbfile))
+}
+
+fn previous_params_path(dbfile: &str) -> PathBuf {
+    PathBuf::from(format!("{}.cipher.old", dbfile))
+}
+
+/// The iterations of `kdf_algorithm` that take about `target_ms` milliseconds on this machine. A
+/// known number of iterations is timed and scaled to the target.
+pub fn benchmark_kdf_iter(kdf_algorithm: CipherAlgorithm, target_ms: u64) -> Result<u32, CMError> {
+    let params = CipherParams {
+        kdf_iter: BENCHMARK_KDF_ITER,
+        kdf_algorithm,
+        ..CipherParams::default()
+    };
+    let salt = new_salt()?;
+    let start = Instant::now();
+    params.derive_key("cman benchmark", &salt);
+    let elapsed = start.elapsed().as_micros().max(1);
+
+    let iterations = BENCHMARK_KDF_ITER as u128 * target_ms as u128 * 1000 / elapsed;
+    // rounded to thousands since the timing is not precise beyond that.
+    let iterations = (iterations / 1000 * 1000).min(u32::MAX as u128) as u32;
+    Ok(iterations.max(MIN_KDF_ITER))
+}
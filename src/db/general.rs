@@ -1,23 +1,142 @@
+use crate::agent;
+use crate::db::cipher::{self, CipherParams};
+use crate::db::key::{self, PasswordSource};
+use crate::db::{migrations, operations};
+use crate::util::argparser::AuditOperation;
 use crate::util::errors::{CMError, CustomError};
 use crate::util::ioutils;
+use crate::util::secret::SecretString;
 
 use rusqlite::{Connection, ErrorCode, OpenFlags, Result};
 
 use std::fs::exists;
+use std::path::Path;
 
+/// Opens and decrypts the database, bringing its schema up to date if it was created by an
+/// older version of cman.
 pub fn get_db_con(dbfile: &str) -> Result<Connection, CMError> {
-    let mut is_new_db: bool = false;
-    let dbcon = match Connection::open_with_flags(dbfile, OpenFlags::SQLITE_OPEN_READ_WRITE) {
-        Ok(con) => con,
-        Err(err) => {
-            is_new_db = true;
-            check_db_error(err, dbfile)?
+    let dbcon = open_db_con(dbfile)?;
+
+    let applied = migrations::run_migrations(&dbcon)?;
+    if let Some(last) = applied.last() {
+        eprintln!("Upgraded database schema to version {}", last.version);
+    }
+    Ok(dbcon)
+}
+
+/// Opens and decrypts the database without applying pending migrations. A master password given
+/// with --password-fd, --password-stdin or --password-env is used first, then the key held by the
+/// agent if one is running, otherwise the master password is read from $CMAN_ASKPASS or the terminal.
+pub fn open_db_con(dbfile: &str) -> Result<Connection, CMError> {
+    if let Err(err) = Connection::open_with_flags(dbfile, OpenFlags::SQLITE_OPEN_READ_WRITE) {
+        return check_db_error(err, dbfile);
+    }
+    if let Some(dbcon) = open_db_con_without_prompt(dbfile)? {
+        return Ok(dbcon);
+    }
+
+    let params = CipherParams::load(dbfile)?;
+    let (master_pass, source) = key::read_master_password("Enter cman master password", false)?;
+    let key = key::composite_key(&master_pass, key::keyfile())?;
+    let (dbcon, raw_key) = open_with_key(dbfile, &key, &params)?;
+    unlocked(dbfile, &key, &raw_key, source);
+    Ok(dbcon)
+}
+
+/// Opens the database if that needs no input from the user: with a master password given with
+/// --password-fd, --password-stdin or --password-env, the key held by the agent or the keyfile
+/// alone. None if none of them unlock it.
+pub fn open_db_con_without_prompt(dbfile: &str) -> Result<Option<Connection>, CMError> {
+    let params = CipherParams::load(dbfile)?;
+    let keyfile = key::keyfile();
+    if let Some((password, source)) = key::given_password()? {
+        let key = key::composite_key(&password, keyfile)?;
+        let (dbcon, raw_key) = open_with_key(dbfile, &key, &params)?;
+        unlocked(dbfile, &key, &raw_key, source);
+        return Ok(Some(dbcon));
+    }
+    // the agent holds the raw key, so neither the password nor PBKDF2 are needed. It can be out of
+    // date, for example because the database was pulled again.
+    if let Some(raw_key) = agent::cached_key(dbfile)
+        && let Ok(dbcon) = open_with_raw_key(dbfile, &raw_key, &params)
+    {
+        key::set_unlock_source(PasswordSource::Agent);
+        return Ok(Some(dbcon));
+    }
+    if keyfile.is_some() {
+        // a database protected by the keyfile alone opens without asking for a password.
+        let key = key::composite_key(&SecretString::default(), keyfile)?;
+        if let Ok((dbcon, raw_key)) = open_with_key(dbfile, &key, &params) {
+            unlocked(dbfile, &key, &raw_key, PasswordSource::Keyfile);
+            return Ok(Some(dbcon));
         }
+    }
+    Ok(None)
+}
+
+/// Opens the database with the raw key derived from `key`, which is returned with the connection.
+/// If that fails and cman db rekdf was interrupted, the parameters the database had before are
+/// tried as well and put back in place if they open it.
+fn open_with_key(
+    dbfile: &str,
+    key: &SecretString,
+    params: &CipherParams,
+) -> Result<(Connection, SecretString), CMError> {
+    let err = match open_with_params(dbfile, key, params) {
+        Ok(opened) => return Ok(opened),
+        Err(e) => e,
     };
+    let Some(previous) = CipherParams::load_previous(dbfile)?.filter(|p| p != params) else {
+        return Err(err);
+    };
+    let Ok(opened) = open_with_params(dbfile, key, &previous) else {
+        return Err(err);
+    };
+    previous.save(dbfile)?;
+    CipherParams::remove_previous(dbfile)?;
+    eprintln!(
+        "Restored the cipher parameters the database had before cman db rekdf was interrupted"
+    );
+    Ok(opened)
+}
 
-    if !is_new_db {
-        decrypt_db(&dbcon)?;
+/// Before cman derived keys itself the password was put into a SQL string literal, where two
+/// single quotes stand for one. A database whose password contains them was keyed with that
+/// reading, so it is tried as well and the database is then rekeyed with the password as typed.
+fn open_with_params(
+    dbfile: &str,
+    key: &SecretString,
+    params: &CipherParams,
+) -> Result<(Connection, SecretString), CMError> {
+    let salt = cipher::read_salt(dbfile)?;
+    let raw_key = cipher::raw_key_value(&params.derive_key(key.expose(), &salt), None);
+    let err = match open_with_raw_key(dbfile, &raw_key, params) {
+        Ok(dbcon) => return Ok((dbcon, raw_key)),
+        Err(e) => e,
+    };
+    if !key.expose().contains("''") {
+        return Err(err);
     }
+
+    let legacy_key = SecretString::new(key.expose().replace("''", "'"));
+    let legacy_raw_key =
+        cipher::raw_key_value(&params.derive_key(legacy_key.expose(), &salt), None);
+    let Ok(dbcon) = open_with_raw_key(dbfile, &legacy_raw_key, params) else {
+        return Err(err);
+    };
+    dbcon.pragma_update(None, "rekey", &raw_key)?;
+    eprintln!("Upgraded the database key to the master password exactly as it is typed");
+    Ok((dbcon, raw_key))
+}
+
+/// Opens the database with a new connection, since a connection keeps the first key it is given.
+fn open_with_raw_key(
+    dbfile: &str,
+    raw_key: &SecretString,
+    params: &CipherParams,
+) -> Result<Connection, CMError> {
+    let dbcon = Connection::open_with_flags(dbfile, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    apply_key(&dbcon, raw_key, params)?;
     Ok(dbcon)
 }
 
@@ -29,7 +148,7 @@ fn check_db_error(err: rusqlite::Error, db_path: &str) -> Result<Connection, CME
             "Could not find Database file. Do you want to initialise it",
         )?;
         if opt {
-            let con = create_new_db(db_path)?;
+            let con = create_new_db(db_path, key::keyfile(), true, &CipherParams::default())?;
             return Ok(con);
         }
     }
@@ -37,16 +156,26 @@ fn check_db_error(err: rusqlite::Error, db_path: &str) -> Result<Connection, CME
     Err(CMError::RusqlilteError(err))
 }
 
-fn decrypt_db(dbcon: &Connection) -> Result<(), CMError> {
-    let master_pass = ioutils::get_terminal_input("Enter cman master password", false, true)?;
-    if master_pass.is_empty() {
-        return Err(CustomError::new("Master password cannot be empty").into());
-    }
+/// Records how the database was unlocked for the audit log and keeps the key for cman db rekdf. The
+/// agent is handed the raw key only, so the password never leaves this process.
+fn unlocked(dbfile: &str, key: &SecretString, raw_key: &SecretString, source: PasswordSource) {
+    key::set_unlock_source(source);
+    key::set_active_key(key);
+    agent::cache_key(dbfile, raw_key);
+}
+
+/// Unlocks the database with a raw key derived from the master password, so that the password
+/// never becomes part of a SQL statement and any password works.
+fn apply_key(
+    dbcon: &Connection,
+    raw_key: &SecretString,
+    params: &CipherParams,
+) -> Result<(), CMError> {
     let pragma_query = "PRAGMA cipher_log = 'off';".to_string();
     dbcon.execute_batch(&pragma_query)?;
 
-    let pragma_query = format!("PRAGMA key = '{}';", &master_pass);
-    dbcon.execute_batch(&pragma_query)?;
+    dbcon.pragma_update(None, "key", raw_key)?;
+    params.apply("main", dbcon)?;
 
     let test_query = "SELECT COUNT(*) FROM sqlite_master";
     if let Err(err) = dbcon.execute_batch(test_query) {
@@ -63,52 +192,303 @@ fn decrypt_db(dbcon: &Connection) -> Result<(), CMError> {
     Ok(())
 }
 
-pub fn create_new_db(path: &str) -> Result<Connection, CMError> {
+/// Creates a database locked with a master password, a keyfile or both. Without `with_password`
+/// the keyfile alone opens it.
+pub fn create_new_db(
+    path: &str,
+    keyfile: Option<&Path>,
+    with_password: bool,
+    params: &CipherParams,
+) -> Result<Connection, CMError> {
     if let Ok(true) = exists(path) {
         return Err(CustomError::new(&format!("File Already Exists at path: {}", path)).into());
     }
-    let create_query = "CREATE TABLE account (\
-	 acc_id INTEGER PRIMARY KEY AUTOINCREMENT,\
-	 acc_name VARCHAR(100) NOT NULL UNIQUE,\
-	 user_name VARCHAR(100),\
-	 password VARCHAR(256)\
-	);\
-	CREATE TABLE api_keys (\
-	api_id INTEGER PRIMARY KEY AUTOINCREMENT,\
-	api_name VARCHAR(100) NOT NULL UNIQUE,\
-	description VARCHAR(100),\
-	user_name VARCHAR(100),\
-	api_key VARCHAR(256)\
-	);";
-
-    let master_pass = ioutils::get_terminal_input(
-        "Enter master password (Make sure to remember it)",
-        true,
-        true,
-    )?;
-    if master_pass.is_empty() {
-        return Err(CustomError::new("Master password cannot be empty").into());
-    }
-    let pragma_query = format!("PRAGMA key = '{}';", &master_pass);
+    if let Some(k) = keyfile {
+        key::check_keyfile(k)?;
+    }
+    let (master_pass, source) = if with_password {
+        key::read_master_password("Enter master password (Make sure to remember it)", true)?
+    } else {
+        (SecretString::default(), PasswordSource::Keyfile)
+    };
+    let key = key::composite_key(&master_pass, keyfile)?;
+    let dbcon = create_with_key(path, &key, params)?;
+    key::set_unlock_source(source);
+    key::set_active_key(&key);
+    println!("Database Created at: {}", path);
+    Ok(dbcon)
+}
+
+/// Creates the database file encrypted with a key derived from `key` and a new salt.
+fn create_with_key(
+    path: &str,
+    key: &SecretString,
+    params: &CipherParams,
+) -> Result<Connection, CMError> {
+    let salt = cipher::new_salt()?;
+    let raw_key = params.derive_key(key.expose(), &salt);
     let dbcon = Connection::open(path)?;
 
-    dbcon.execute_batch(&pragma_query)?;
-    dbcon.execute_batch(create_query)?;
-    println!("Database Created at: {}", path);
+    dbcon.pragma_update(None, "key", cipher::raw_key_value(&raw_key, Some(&salt)))?;
+    params.apply("main", &dbcon)?;
+    params.save(path)?;
+    migrations::run_migrations(&dbcon)?;
     Ok(dbcon)
 }
 
-pub fn change_db_password(dbcon: &Connection) -> Result<(), CMError> {
-    let master_pass = ioutils::get_terminal_input(
-        "Enter new master password (Make sure to remember it)",
-        true,
-        true,
-    )?;
-    if master_pass.is_empty() {
-        return Err(CustomError::new("Master password cannot be empty").into());
+/// Locks the database with a new master password, keyfile or both. Without `with_password` the
+/// keyfile alone opens it.
+pub fn change_db_password(
+    dbcon: &Connection,
+    keyfile: Option<&Path>,
+    with_password: bool,
+) -> Result<(), CMError> {
+    if let Some(k) = keyfile {
+        key::check_keyfile(k)?;
     }
-    let pragma_query = format!("PRAGMA rekey = '{}';", &master_pass);
+    let master_pass = if with_password {
+        let pass = ioutils::get_private_input(
+            "Enter new master password (Make sure to remember it)",
+            true,
+        )?;
+        if pass.is_empty() {
+            return Err(CustomError::new("Master password cannot be empty").into());
+        }
+        pass
+    } else {
+        SecretString::default()
+    };
+    let Some(path) = dbcon.path() else {
+        return Err(CustomError::new("Only a database file can be rekeyed").into());
+    };
+    let key = key::composite_key(&master_pass, keyfile)?;
+    let params = CipherParams::load(path)?;
+    let raw_key = cipher::raw_key_value(
+        &params.derive_key(key.expose(), &cipher::read_salt(path)?),
+        None,
+    );
 
-    dbcon.execute_batch(&pragma_query)?;
+    dbcon.pragma_update(None, "rekey", &raw_key)?;
+    operations::log_operation(AuditOperation::Master, None, None, None, dbcon)?;
+    key::set_active_key(&key);
+    agent::cache_key(path, &raw_key);
     Ok(())
 }
+
+/// Re-encrypts the database with new cipher parameters. SQLCipher cannot change them in place so
+/// the database is exported to a new file keyed the same way, which then replaces it.
+pub fn rekdf_db(dbcon: &Connection, params: &CipherParams) -> Result<(), CMError> {
+    let Some(path) = dbcon.path().map(|p| p.to_string()) else {
+        return Err(CustomError::new("Only a database file can be re-encrypted").into());
+    };
+    let key = match key::active_key() {
+        Some(k) => k,
+        // the agent only holds the raw key for the current parameters, so the password is needed.
+        None => {
+            let (master_pass, _) = key::read_master_password("Enter cman master password", false)?;
+            let key = key::composite_key(&master_pass, key::keyfile())?;
+            open_with_key(&path, &key, &CipherParams::load(&path)?)?;
+            key
+        }
+    };
+    let new_path = format!("{}.rekdf", path);
+    // the database is opened without permission to create files, so attaching needs one to exist.
+    std::fs::File::create(&new_path)?;
+
+    // a new salt, since the old one belongs to the old key.
+    let salt = cipher::new_salt()?;
+    let raw_key = cipher::raw_key_value(&params.derive_key(key.expose(), &salt), Some(&salt));
+    let version = migrations::get_schema_version(dbcon)?;
+    dbcon.execute("ATTACH DATABASE ?1 AS rekdf KEY ?2;", (&new_path, &raw_key))?;
+    let exported = params.apply("rekdf", dbcon).and_then(|_| {
+        dbcon.query_row("SELECT sqlcipher_export('rekdf');", [], |_| Ok(()))?;
+        dbcon.pragma_update(Some("rekdf"), "user_version", version)?;
+        Ok(())
+    });
+    dbcon.execute_batch("DETACH DATABASE rekdf;")?;
+    if let Err(e) = exported {
+        let _ = std::fs::remove_file(&new_path);
+        return Err(e);
+    }
+
+    // the parameters are saved before the file is replaced. Should cman stop in between, the old
+    // ones are still there for open_with_key to fall back to.
+    CipherParams::load(&path)?.save_previous(&path)?;
+    params.save(&path)?;
+    std::fs::rename(&new_path, &path)?;
+    CipherParams::remove_previous(&path)?;
+
+    // the entry goes into the new file, which also checks that it opens with the new parameters.
+    let (new_dbcon, raw_key) = open_with_key(&path, &key, params)?;
+    operations::log_operation(AuditOperation::Rekdf, None, None, None, &new_dbcon)?;
+    agent::cache_key(&path, &raw_key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::argparser::CipherAlgorithm;
+
+    use tempfile::TempDir;
+
+    /// Few iterations so that the tests do not spend their time in PBKDF2.
+    const FAST_PARAMS: CipherParams = CipherParams {
+        kdf_iter: 1000,
+        kdf_algorithm: CipherAlgorithm::Sha512,
+        hmac_algorithm: CipherAlgorithm::Sha512,
+        page_size: cipher::DEFAULT_PAGE_SIZE,
+    };
+
+    fn db_path(dir: &TempDir) -> String {
+        dir.path().join("test.db").to_string_lossy().to_string()
+    }
+
+    fn account_count(dbcon: &Connection) -> i64 {
+        dbcon
+            .query_row("SELECT COUNT(*) FROM account", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn passwords_round_trip() {
+        let long = "long passphrase ".repeat(1000);
+        let passwords = [
+            "simple",
+            "it's",
+            "two '' quotes and ''' three",
+            "\"double\" quotes",
+            "back\\slash\\",
+            "'; DROP TABLE account; --",
+            "x'00'",
+            "🔐🦀 pässwörd ✓",
+            "tab\tand\nnewline",
+            &long,
+        ];
+        for password in passwords {
+            let dir = TempDir::new().unwrap();
+            let path = db_path(&dir);
+            let key = SecretString::from(password);
+            let dbcon = create_with_key(&path, &key, &FAST_PARAMS).unwrap();
+            dbcon
+                .execute(
+                    "INSERT INTO account (acc_name, user_name, password) VALUES ('a', 'u', 'p')",
+                    [],
+                )
+                .unwrap();
+            drop(dbcon);
+
+            let params = CipherParams::load(&path).unwrap();
+            assert_eq!(params, FAST_PARAMS);
+            let (dbcon, raw_key) = open_with_key(&path, &key, &params)
+                .unwrap_or_else(|e| panic!("{:?} did not open the database: {}", password, e));
+            assert_eq!(account_count(&dbcon), 1, "{:?}", password);
+            drop(dbcon);
+
+            // the raw key the agent is given opens it without the password.
+            assert!(!raw_key.expose().contains(password));
+            let dbcon = open_with_raw_key(&path, &raw_key, &params).unwrap();
+            assert_eq!(account_count(&dbcon), 1, "{:?}", password);
+        }
+    }
+
+    #[test]
+    fn wrong_password_fails() {
+        let dir = TempDir::new().unwrap();
+        let path = db_path(&dir);
+        create_with_key(&path, &"correct horse".into(), &FAST_PARAMS).unwrap();
+
+        for wrong in ["correct hors", "Correct horse", "", "correct horse "] {
+            assert!(
+                open_with_key(&path, &wrong.into(), &FAST_PARAMS).is_err(),
+                "{:?} opened the database",
+                wrong
+            );
+        }
+        // the right password with other parameters does not open it either.
+        let params = CipherParams {
+            kdf_iter: 2000,
+            ..FAST_PARAMS
+        };
+        assert!(open_with_key(&path, &"correct horse".into(), &params).is_err());
+    }
+
+    #[test]
+    fn interrupted_rekdf_falls_back_to_previous_params() {
+        let dir = TempDir::new().unwrap();
+        let path = db_path(&dir);
+        let key = SecretString::from("pw");
+        create_with_key(&path, &key, &FAST_PARAMS).unwrap();
+
+        // stopped after the new parameters were saved but before the database was replaced.
+        FAST_PARAMS.save_previous(&path).unwrap();
+        let new_params = CipherParams {
+            kdf_iter: 2000,
+            ..FAST_PARAMS
+        };
+        new_params.save(&path).unwrap();
+
+        let (dbcon, _) = open_with_key(&path, &key, &new_params).unwrap();
+        assert_eq!(account_count(&dbcon), 0);
+        assert_eq!(CipherParams::load(&path).unwrap(), FAST_PARAMS);
+        assert_eq!(CipherParams::load_previous(&path).unwrap(), None);
+        assert!(open_with_key(&path, &"wrong".into(), &FAST_PARAMS).is_err());
+    }
+
+    /// Creates a database the way cman did before it derived keys itself, with the password in a
+    /// SQL string literal. It uses the SQLCipher defaults apart from the iterations.
+    fn create_legacy_db(path: &str, password: &str) {
+        let dbcon = Connection::open(path).unwrap();
+        dbcon
+            .execute_batch(&format!(
+                "PRAGMA key = '{}'; PRAGMA kdf_iter = {};",
+                password, FAST_PARAMS.kdf_iter
+            ))
+            .unwrap();
+        dbcon
+            .execute_batch("CREATE TABLE legacy (value TEXT); INSERT INTO legacy VALUES ('kept');")
+            .unwrap();
+    }
+
+    fn legacy_value(dbcon: &Connection) -> String {
+        dbcon
+            .query_row("SELECT value FROM legacy", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn legacy_database_opens() {
+        let dir = TempDir::new().unwrap();
+        let path = db_path(&dir);
+        create_legacy_db(&path, "old password");
+
+        assert_eq!(CipherParams::load(&path).unwrap(), CipherParams::default());
+        let params = FAST_PARAMS;
+        let (dbcon, _) = open_with_key(&path, &"old password".into(), &params).unwrap();
+        assert_eq!(legacy_value(&dbcon), "kept");
+        assert!(open_with_key(&path, &"old passwort".into(), &params).is_err());
+    }
+
+    #[test]
+    fn legacy_quotes_are_rekeyed() {
+        let dir = TempDir::new().unwrap();
+        let path = db_path(&dir);
+        // typed as it''s, which the SQL literal read as it's.
+        create_legacy_db(&path, "it''s");
+        let typed = SecretString::from("it''s");
+        let params = FAST_PARAMS;
+        let salt = cipher::read_salt(&path).unwrap();
+        let typed_raw_key = cipher::raw_key_value(&params.derive_key(typed.expose(), &salt), None);
+        assert!(open_with_raw_key(&path, &typed_raw_key, &params).is_err());
+
+        let (dbcon, raw_key) = open_with_key(&path, &typed, &params).unwrap();
+        assert_eq!(legacy_value(&dbcon), "kept");
+        assert_eq!(raw_key, typed_raw_key);
+        drop(dbcon);
+
+        // the database now opens with the password exactly as typed, not the SQL reading of it.
+        let dbcon = open_with_raw_key(&path, &typed_raw_key, &params).unwrap();
+        assert_eq!(legacy_value(&dbcon), "kept");
+        assert!(open_with_key(&path, &"it's".into(), &params).is_err());
+    }
+}
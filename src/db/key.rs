@@ -0,0 +1,291 @@
+use crate::util::errors::{CMError, CustomError};
+use crate::util::ioutils;
+use crate::util::secret::SecretString;
+
+use rand::TryRngCore;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+const ASKPASS_ENV_VAR: &str = "CMAN_ASKPASS";
+/// The number of random bytes in a keyfile made by cman keyfile new.
+const KEYFILE_LEN: usize = 64;
+
+/// Where the master password given to cman came from. It is recorded with every audit log entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordSource {
+    Prompt,
+    Fd,
+    Stdin,
+    Env,
+    Askpass,
+    Agent,
+    Keyfile,
+}
+
+/// A way of passing the master password chosen on the command line. The password itself is
+/// never accepted as an argument so that it cannot show up in the process list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordOption {
+    Fd(u32),
+    Stdin,
+    Env(String),
+}
+
+static PASSWORD_OPTION: OnceLock<PasswordOption> = OnceLock::new();
+/// Passwords from a file descriptor or stdin can only be read once.
+static GIVEN_PASSWORD: OnceLock<SecretString> = OnceLock::new();
+static UNLOCK_SOURCE: Mutex<Option<PasswordSource>> = Mutex::new(None);
+static KEYFILE: OnceLock<PathBuf> = OnceLock::new();
+static ACTIVE_KEY: Mutex<Option<SecretString>> = Mutex::new(None);
+
+impl PasswordSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Prompt => "prompt",
+            Self::Fd => "fd",
+            Self::Stdin => "stdin",
+            Self::Env => "env",
+            Self::Askpass => "askpass",
+            Self::Agent => "agent",
+            Self::Keyfile => "keyfile",
+        }
+    }
+}
+
+/// Makes cman read the master password from the given source instead of asking for it.
+pub fn set_password_option(option: PasswordOption) {
+    let _ = PASSWORD_OPTION.set(option);
+}
+
+/// Makes the keyfile part of the key of the database that is opened.
+pub fn set_keyfile(path: &Path) {
+    let _ = KEYFILE.set(path.to_path_buf());
+}
+
+/// The keyfile of the database that is opened, if it has one.
+pub fn keyfile() -> Option<&'static Path> {
+    KEYFILE.get().map(|p| p.as_path())
+}
+
+/// Records how the database was unlocked for the audit log.
+pub fn set_unlock_source(source: PasswordSource) {
+    if let Ok(mut guard) = UNLOCK_SOURCE.lock() {
+        *guard = Some(source);
+    }
+}
+
+pub fn unlock_source() -> Option<PasswordSource> {
+    UNLOCK_SOURCE.lock().ok().and_then(|guard| *guard)
+}
+
+/// Remembers the key the open database is encrypted with, for operations that re-encrypt it.
+pub fn set_active_key(key: &SecretString) {
+    if let Ok(mut guard) = ACTIVE_KEY.lock() {
+        *guard = Some(key.clone());
+    }
+}
+
+pub fn active_key() -> Option<SecretString> {
+    ACTIVE_KEY.lock().ok().and_then(|guard| guard.clone())
+}
+
+/// The master password given with --password-fd, --password-stdin or --password-env, if any.
+pub fn given_password() -> Result<Option<(SecretString, PasswordSource)>, CMError> {
+    let Some(option) = PASSWORD_OPTION.get() else {
+        return Ok(None);
+    };
+    let source = match option {
+        PasswordOption::Fd(_) => PasswordSource::Fd,
+        PasswordOption::Stdin => PasswordSource::Stdin,
+        PasswordOption::Env(_) => PasswordSource::Env,
+    };
+    if let Some(password) = GIVEN_PASSWORD.get() {
+        return Ok(Some((password.clone(), source)));
+    }
+
+    let password = match option {
+        PasswordOption::Fd(fd) => {
+            let mut file = std::fs::File::open(format!("/dev/fd/{}", fd)).map_err(|e| {
+                CMError::from(CustomError::new(&format!(
+                    "Could not read the master password from file descriptor {}: {}",
+                    fd, e
+                )))
+            })?;
+            read_first_line(&mut file)?
+        }
+        PasswordOption::Stdin => {
+            // stdin is read through its shared buffer so that anything after the first line is
+            // still there for commands that read stdin, like adding a note.
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            trim_line_ending(line)
+        }
+        PasswordOption::Env(name) => match std::env::var(name) {
+            Ok(p) => p.into(),
+            Err(_) => {
+                return Err(CustomError::new(&format!(
+                    "The environment variable {} is not set",
+                    name
+                ))
+                .into());
+            }
+        },
+    };
+    if password.is_empty() {
+        return Err(CustomError::new("Master password cannot be empty").into());
+    }
+    let _ = GIVEN_PASSWORD.set(password.clone());
+    Ok(Some((password, source)))
+}
+
+/// Gets the master password from the option given on the command line, the $CMAN_ASKPASS helper
+/// or by asking on the terminal, in that order. New passwords are confirmed on the terminal.
+pub fn read_master_password(
+    prompt: &str,
+    confirm: bool,
+) -> Result<(SecretString, PasswordSource), CMError> {
+    if let Some(given) = given_password()? {
+        return Ok(given);
+    }
+
+    let (password, source) = match askpass_program() {
+        Some(program) => (run_askpass(&program, prompt)?, PasswordSource::Askpass),
+        None => (
+            ioutils::get_private_input(prompt, confirm)?,
+            PasswordSource::Prompt,
+        ),
+    };
+    if password.is_empty() {
+        return Err(CustomError::new("Master password cannot be empty").into());
+    }
+    Ok((password, source))
+}
+
+fn askpass_program() -> Option<String> {
+    std::env::var(ASKPASS_ENV_VAR)
+        .ok()
+        .filter(|p| !p.is_empty())
+}
+
+/// Runs an askpass helper the way ssh and git do: the prompt is its only argument and the first
+/// line it prints is the password.
+fn run_askpass(program: &str, prompt: &str) -> Result<SecretString, CMError> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| {
+            CMError::from(CustomError::new(&format!(
+                "Could not run the askpass program {}: {}",
+                program, e
+            )))
+        })?;
+    if !output.status.success() {
+        return Err(CustomError::new(&format!(
+            "The askpass program {} did not return a password",
+            program
+        ))
+        .into());
+    }
+    let stdout = Zeroizing::new(output.stdout);
+    read_first_line(&mut stdout.as_slice())
+}
+
+/// Reads up to the first line ending one byte at a time so that nothing after it is consumed.
+fn read_first_line(reader: &mut impl Read) -> Result<SecretString, CMError> {
+    let mut bytes = Vec::new();
+    let mut byte = [0u8; 1];
+    while reader.read(&mut byte)? == 1 && byte[0] != b'\n' {
+        bytes.push(byte[0]);
+    }
+    let line = String::from_utf8(bytes)
+        .map_err(|_| CustomError::new("The master password is not valid UTF-8"))?;
+    Ok(trim_line_ending(line))
+}
+
+fn trim_line_ending(mut line: String) -> SecretString {
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    line.into()
+}
+
+/// The key handed to SQLCipher. Without a keyfile it is the master password itself, so databases
+/// created before keyfiles existed still open. With one it is the hex encoded sha256 of the hashes
+/// of the password and the keyfile, the way KeePass combines them, and an empty password means the
+/// keyfile alone protects the database.
+pub fn composite_key(
+    password: &SecretString,
+    keyfile: Option<&Path>,
+) -> Result<SecretString, CMError> {
+    let Some(keyfile) = keyfile else {
+        return Ok(password.clone());
+    };
+
+    let mut hasher = Sha256::new();
+    if !password.is_empty() {
+        hasher.update(Sha256::digest(password.expose().as_bytes()));
+    }
+    hasher.update(Sha256::digest(read_keyfile(keyfile)?));
+    let key: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(key.into())
+}
+
+fn read_keyfile(path: &Path) -> Result<Zeroizing<Vec<u8>>, CMError> {
+    let contents = std::fs::read(path).map_err(|e| {
+        CMError::from(CustomError::new(&format!(
+            "Could not read the keyfile {}: {}",
+            path.display(),
+            e
+        )))
+    })?;
+    if contents.is_empty() {
+        return Err(CustomError::new(&format!("The keyfile {} is empty", path.display())).into());
+    }
+    Ok(Zeroizing::new(contents))
+}
+
+/// Checks that a file can be used as a keyfile before a database is locked with it.
+pub fn check_keyfile(path: &Path) -> Result<(), CMError> {
+    read_keyfile(path).map(|_| ())
+}
+
+/// Writes a new keyfile of random bytes that only the user can read. An existing file is never
+/// overwritten since the databases it protects could not be opened anymore.
+pub fn create_keyfile(path: &Path) -> Result<(), CMError> {
+    let mut bytes = Zeroizing::new([0u8; KEYFILE_LEN]);
+    OsRng
+        .try_fill_bytes(bytes.as_mut())
+        .map_err(|_| CustomError::new("Could not get random bytes for the keyfile"))?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| {
+        CMError::from(CustomError::new(&format!(
+            "Could not create the keyfile {}: {}",
+            path.display(),
+            e
+        )))
+    })?;
+    file.write_all(bytes.as_ref())?;
+    Ok(())
+}
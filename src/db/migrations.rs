@@ -0,0 +1,210 @@
+use crate::util::errors::{CMError, CustomError};
+
+use rusqlite::Connection;
+
+/// A single schema upgrade step. `version` is the value `PRAGMA user_version` holds once the
+/// step has been applied.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// All schema upgrade steps in the order they have to be applied. Never edit a step that has
+/// already been released, append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create the account and api_keys tables",
+        // IF NOT EXISTS lets databases created before schema versioning existed be stamped with
+        // version 1 without touching their data.
+        sql: "CREATE TABLE IF NOT EXISTS account (\
+	 acc_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+	 acc_name VARCHAR(100) NOT NULL UNIQUE,\
+	 user_name VARCHAR(100),\
+	 password VARCHAR(256)\
+	);\
+	CREATE TABLE IF NOT EXISTS api_keys (\
+	api_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+	api_name VARCHAR(100) NOT NULL UNIQUE,\
+	description VARCHAR(100),\
+	user_name VARCHAR(100),\
+	api_key VARCHAR(256)\
+	);",
+    },
+    Migration {
+        version: 2,
+        description: "Record created, updated and last accessed times on secrets",
+        // existing rows keep NULL timestamps since their real creation time is unknown.
+        sql: "ALTER TABLE account ADD COLUMN created_at TEXT;\
+        ALTER TABLE account ADD COLUMN updated_at TEXT;\
+        ALTER TABLE account ADD COLUMN accessed_at TEXT;\
+        ALTER TABLE api_keys ADD COLUMN created_at TEXT;\
+        ALTER TABLE api_keys ADD COLUMN updated_at TEXT;\
+        ALTER TABLE api_keys ADD COLUMN accessed_at TEXT;",
+    },
+    Migration {
+        version: 3,
+        description: "Keep a history of previous passwords and api keys",
+        sql: "CREATE TABLE secret_history (\
+        hist_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+        secret_type VARCHAR(10) NOT NULL,\
+        secret_id INTEGER NOT NULL,\
+        value VARCHAR(256),\
+        replaced_at TEXT NOT NULL\
+        );\
+        CREATE INDEX secret_history_secret ON secret_history(secret_type, secret_id);",
+    },
+    Migration {
+        version: 4,
+        description: "Add tags for organising secrets",
+        sql: "CREATE TABLE tags (\
+        tag_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+        tag_name VARCHAR(100) NOT NULL UNIQUE\
+        );\
+        CREATE TABLE secret_tags (\
+        secret_type VARCHAR(10) NOT NULL,\
+        secret_id INTEGER NOT NULL,\
+        tag_id INTEGER NOT NULL REFERENCES tags(tag_id),\
+        PRIMARY KEY (secret_type, secret_id, tag_id)\
+        );",
+    },
+    Migration {
+        version: 5,
+        description: "Add notes and custom fields to secrets",
+        sql: "ALTER TABLE account ADD COLUMN notes TEXT;\
+        ALTER TABLE api_keys ADD COLUMN notes TEXT;\
+        CREATE TABLE custom_fields (\
+        secret_type VARCHAR(10) NOT NULL,\
+        secret_id INTEGER NOT NULL,\
+        field_key VARCHAR(100) NOT NULL,\
+        field_value TEXT,\
+        sensitive INTEGER NOT NULL DEFAULT 0,\
+        PRIMARY KEY (secret_type, secret_id, field_key)\
+        );",
+    },
+    Migration {
+        version: 6,
+        description: "Add the ssh_keys table",
+        sql: "CREATE TABLE ssh_keys (\
+        ssh_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+        ssh_name VARCHAR(100) NOT NULL UNIQUE,\
+        comment VARCHAR(256) NOT NULL DEFAULT '',\
+        public_key TEXT NOT NULL,\
+        private_key TEXT NOT NULL,\
+        passphrase VARCHAR(256) NOT NULL DEFAULT '',\
+        notes TEXT,\
+        created_at TEXT,\
+        updated_at TEXT,\
+        accessed_at TEXT\
+        );",
+    },
+    Migration {
+        version: 7,
+        description: "Store two factor authentication seeds on login credentials",
+        sql: "ALTER TABLE account ADD COLUMN otp TEXT;",
+    },
+    Migration {
+        version: 8,
+        description: "Add the secure_notes table",
+        sql: "CREATE TABLE secure_notes (\
+        note_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+        note_name VARCHAR(100) NOT NULL UNIQUE,\
+        body TEXT NOT NULL,\
+        notes TEXT,\
+        created_at TEXT,\
+        updated_at TEXT,\
+        accessed_at TEXT\
+        );",
+    },
+    Migration {
+        version: 9,
+        description: "Add file attachments to secrets",
+        sql: "CREATE TABLE attachments (\
+        secret_type VARCHAR(10) NOT NULL,\
+        secret_id INTEGER NOT NULL,\
+        file_name VARCHAR(256) NOT NULL,\
+        data BLOB NOT NULL,\
+        added_at TEXT NOT NULL,\
+        PRIMARY KEY (secret_type, secret_id, file_name)\
+        );",
+    },
+    Migration {
+        version: 10,
+        description: "Move deleted secrets to a trash instead of removing them",
+        sql: "ALTER TABLE account ADD COLUMN deleted_at TEXT;\
+        ALTER TABLE api_keys ADD COLUMN deleted_at TEXT;\
+        ALTER TABLE ssh_keys ADD COLUMN deleted_at TEXT;\
+        ALTER TABLE secure_notes ADD COLUMN deleted_at TEXT;",
+    },
+    Migration {
+        version: 11,
+        description: "Add an append-only audit log of vault operations",
+        sql: "CREATE TABLE audit_log (\
+        log_id INTEGER PRIMARY KEY AUTOINCREMENT,\
+        logged_at TEXT NOT NULL,\
+        actor VARCHAR(100) NOT NULL,\
+        operation VARCHAR(20) NOT NULL,\
+        secret_type VARCHAR(10),\
+        secret_name VARCHAR(100),\
+        field VARCHAR(256)\
+        );\
+        CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log \
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;\
+        CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log \
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+    },
+    Migration {
+        version: 12,
+        description: "Add a full text search index of secret names and details",
+        sql: "CREATE VIRTUAL TABLE search_index USING fts5(\
+        secret_type UNINDEXED,\
+        secret_name UNINDEXED,\
+        content\
+        );\
+        CREATE TABLE search_index_state (indexed_log_id INTEGER NOT NULL);",
+    },
+    Migration {
+        version: 13,
+        description: "Record how the database was unlocked with each audit log entry",
+        sql: "ALTER TABLE audit_log ADD COLUMN unlocked_with VARCHAR(10);",
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn get_schema_version(dbcon: &Connection) -> Result<u32, CMError> {
+    let version = dbcon.query_row("PRAGMA user_version;", [], |row| row.get::<_, u32>(0))?;
+    Ok(version)
+}
+
+pub fn get_pending_migrations(dbcon: &Connection) -> Result<Vec<&'static Migration>, CMError> {
+    let current = get_schema_version(dbcon)?;
+    if current > latest_version() {
+        return Err(CustomError::new(&format!(
+            "Database schema version {} is newer than this version of cman supports (version {}). Please upgrade cman.",
+            current,
+            latest_version()
+        ))
+        .into());
+    }
+
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// Applies every pending migration, each one in its own transaction so that a failing step
+/// leaves the database at the last good version. Returns the migrations that were applied.
+pub fn run_migrations(dbcon: &Connection) -> Result<Vec<&'static Migration>, CMError> {
+    let pending = get_pending_migrations(dbcon)?;
+
+    for migration in &pending {
+        let tx = dbcon.unchecked_transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(pending)
+}
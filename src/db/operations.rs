@@ -1,14 +1,389 @@
-use crate::objects::{APIObj, AccountObj, Secret};
-use crate::util::argparser::FieldType;
+use crate::db::key;
+use crate::objects::{
+    APIObj, AccountObj, Attachment, AuditEntry, CustomField, HistoryEntry, NoteObj, Secret, SshObj,
+    TrashEntry,
+};
+use crate::util::argparser::{AuditOperation, FieldType, SecretType};
 use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
 
-use rusqlite::Connection;
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension, Row};
+
+/// Where a secret type lives in the database. Tables shared by all secret types such as
+/// secret_history refer to a secret by its kind and row id.
+struct SecretTable {
+    kind: &'static str,
+    table: &'static str,
+    id_col: &'static str,
+    name_col: &'static str,
+}
+
+fn secret_table(sec_type: SecretType) -> SecretTable {
+    match sec_type {
+        SecretType::Login => SecretTable {
+            kind: "login",
+            table: "account",
+            id_col: "acc_id",
+            name_col: "acc_name",
+        },
+        SecretType::Api => SecretTable {
+            kind: "api",
+            table: "api_keys",
+            id_col: "api_id",
+            name_col: "api_name",
+        },
+        SecretType::Ssh => SecretTable {
+            kind: "ssh",
+            table: "ssh_keys",
+            id_col: "ssh_id",
+            name_col: "ssh_name",
+        },
+        SecretType::Note => SecretTable {
+            kind: "note",
+            table: "secure_notes",
+            id_col: "note_id",
+            name_col: "note_name",
+        },
+    }
+}
+
+/// Columns every secret type has. The tags and custom fields of the secret are read in the same
+/// query through subqueries on the shared tables.
+fn shared_columns(t: &SecretTable) -> String {
+    format!(
+        "created_at, updated_at, accessed_at, notes, \
+        (SELECT group_concat(tg.tag_name, ',') FROM secret_tags st JOIN tags tg ON tg.tag_id = st.tag_id \
+        WHERE st.secret_type = '{kind}' AND st.secret_id = {table}.{id}), \
+        (SELECT json_group_array(json_object('key', cf.field_key, 'value', cf.field_value, \
+        'sensitive', json(CASE WHEN cf.sensitive THEN 'true' ELSE 'false' END))) \
+        FROM custom_fields cf WHERE cf.secret_type = '{kind}' AND cf.secret_id = {table}.{id})",
+        kind = t.kind,
+        table = t.table,
+        id = t.id_col
+    )
+}
+
+fn account_columns() -> String {
+    format!(
+        "acc_name, user_name, password, otp, {}",
+        shared_columns(&secret_table(SecretType::Login))
+    )
+}
+
+fn apikey_columns() -> String {
+    format!(
+        "api_name, description, user_name, api_key, {}",
+        shared_columns(&secret_table(SecretType::Api))
+    )
+}
+
+fn ssh_key_columns() -> String {
+    format!(
+        "ssh_name, comment, public_key, private_key, passphrase, {}",
+        shared_columns(&secret_table(SecretType::Ssh))
+    )
+}
+
+fn note_columns() -> String {
+    format!(
+        "note_name, body, {}",
+        shared_columns(&secret_table(SecretType::Note))
+    )
+}
+
+/// Custom fields are read as a json array built by sqlite.
+fn custom_fields_from_column(fields: Option<SecretString>) -> Vec<CustomField> {
+    let mut fields: Vec<CustomField> = match fields {
+        Some(f) => serde_json::from_str(f.expose()).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    fields.sort_by(|a, b| a.key.cmp(&b.key));
+    fields
+}
+
+/// Tags are read as one comma separated column, tag names are not allowed to contain commas.
+fn tags_from_column(tags: Option<String>) -> Vec<String> {
+    let mut tags: Vec<String> = match tags {
+        Some(t) => t.split(',').map(|t| t.to_string()).collect(),
+        None => Vec::new(),
+    };
+    tags.sort();
+    tags
+}
+
+fn account_from_row(row: &Row) -> Result<AccountObj, rusqlite::Error> {
+    Ok(AccountObj {
+        account_name: row.get(0)?,
+        user_name: row.get(1)?,
+        password: row.get(2)?,
+        otp: row.get::<_, Option<SecretString>>(3)?.unwrap_or_default(),
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        accessed_at: row.get(6)?,
+        notes: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+        tags: tags_from_column(row.get(8)?),
+        custom_fields: custom_fields_from_column(row.get(9)?),
+    })
+}
+
+fn apikey_from_row(row: &Row) -> Result<APIObj, rusqlite::Error> {
+    Ok(APIObj {
+        api_name: row.get(0)?,
+        description: row.get(1)?,
+        user_name: row.get(2)?,
+        api_key: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        accessed_at: row.get(6)?,
+        notes: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+        tags: tags_from_column(row.get(8)?),
+        custom_fields: custom_fields_from_column(row.get(9)?),
+    })
+}
+
+fn ssh_key_from_row(row: &Row) -> Result<SshObj, rusqlite::Error> {
+    Ok(SshObj {
+        ssh_name: row.get(0)?,
+        comment: row.get(1)?,
+        public_key: row.get(2)?,
+        private_key: row.get(3)?,
+        passphrase: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        accessed_at: row.get(7)?,
+        notes: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        tags: tags_from_column(row.get(9)?),
+        custom_fields: custom_fields_from_column(row.get(10)?),
+    })
+}
+
+fn note_from_row(row: &Row) -> Result<NoteObj, rusqlite::Error> {
+    Ok(NoteObj {
+        note_name: row.get(0)?,
+        body: row.get(1)?,
+        created_at: row.get(2)?,
+        updated_at: row.get(3)?,
+        accessed_at: row.get(4)?,
+        notes: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+        tags: tags_from_column(row.get(6)?),
+        custom_fields: custom_fields_from_column(row.get(7)?),
+    })
+}
+
+pub fn check_secret_exists(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<bool, rusqlite::Error> {
+    match sec_type {
+        SecretType::Login => check_account_exists(secret_name, dbcon),
+        SecretType::Api => check_apikey_exists(secret_name, dbcon),
+        SecretType::Ssh => check_ssh_key_exists(secret_name, dbcon),
+        SecretType::Note => check_note_exists(secret_name, dbcon),
+    }
+}
+
+/// The outcome of looking up a secret by name without knowing its type.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SecretLookup {
+    /// The secret to use. With a known type its existence is left for the caller to check.
+    Found(SecretType, String),
+
+    /// No secret of any type has the name.
+    NotFound(String),
+
+    /// Secrets of more than one type share the name.
+    Ambiguous(Vec<SecretType>, String),
+}
+
+/// Finds a secret by name across all secret types. The name can be qualified with its type as in
+/// api/stripe which, like passing sec_type, restricts the lookup to that type.
+pub fn lookup_secret(
+    name: &str,
+    sec_type: Option<SecretType>,
+    dbcon: &Connection,
+) -> Result<SecretLookup, CMError> {
+    let (qualifier, name) = split_qualified_name(name);
+    let sec_type = match (qualifier, sec_type) {
+        (Some(q), Some(t)) if q != t => {
+            return Err(CustomError::new(&format!(
+                "{} is qualified with a different type than the one given with --type",
+                name
+            ))
+            .into());
+        }
+        (q, t) => q.or(t),
+    };
+    if let Some(t) = sec_type {
+        return Ok(SecretLookup::Found(t, name.to_string()));
+    }
+
+    let mut types = Vec::new();
+    for t in SecretType::value_variants() {
+        if check_secret_exists(*t, name, dbcon)? {
+            types.push(*t);
+        }
+    }
+    Ok(match types.len() {
+        0 => SecretLookup::NotFound(name.to_string()),
+        1 => SecretLookup::Found(types[0], name.to_string()),
+        _ => SecretLookup::Ambiguous(types, name.to_string()),
+    })
+}
+
+/// Splits a type/name qualified name. Names whose prefix is not a secret type are left whole.
+fn split_qualified_name(name: &str) -> (Option<SecretType>, &str) {
+    if let Some((prefix, rest)) = name.split_once('/')
+        && !rest.is_empty()
+        && let Some(t) = SecretType::value_variants()
+            .iter()
+            .find(|t| secret_table(**t).kind == prefix)
+    {
+        return (Some(*t), rest);
+    }
+    (None, name)
+}
+
+pub fn get_secret_from_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<Secret, CMError> {
+    match sec_type {
+        SecretType::Login => get_account_from_db(secret_name, dbcon),
+        SecretType::Api => get_apikey_from_db(secret_name, dbcon),
+        SecretType::Ssh => get_ssh_key_from_db(secret_name, dbcon),
+        SecretType::Note => get_note_from_db(secret_name, dbcon),
+    }
+}
+
+pub fn get_all_secrets_from_db(
+    sec_type: SecretType,
+    dbcon: &Connection,
+) -> Result<Vec<Secret>, rusqlite::Error> {
+    match sec_type {
+        SecretType::Login => get_all_accounts_from_db(dbcon),
+        SecretType::Api => get_all_apikeys_from_db(dbcon),
+        SecretType::Ssh => get_all_ssh_keys_from_db(dbcon),
+        SecretType::Note => get_all_notes_from_db(dbcon),
+    }
+}
+
+pub fn delete_secret_from_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    match sec_type {
+        SecretType::Login => delete_account_from_db(secret_name, dbcon),
+        SecretType::Api => delete_apikey_from_db(secret_name, dbcon),
+        SecretType::Ssh => delete_ssh_key_from_db(secret_name, dbcon),
+        SecretType::Note => delete_note_from_db(secret_name, dbcon),
+    }
+}
+
+/// Moves a secret to the trash. Everything stored with it is kept until the trash is purged.
+pub fn trash_secret(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "UPDATE {} SET deleted_at = datetime('now') WHERE {} = ?1 AND deleted_at IS NULL;",
+        t.table, t.name_col
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, [secret_name])?;
+    if affected_rows > 0 {
+        log_operation(
+            AuditOperation::Trash,
+            Some(sec_type),
+            Some(secret_name),
+            None,
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn restore_secret_from_trash(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "UPDATE {} SET deleted_at = NULL WHERE {} = ?1 AND deleted_at IS NOT NULL;",
+        t.table, t.name_col
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, [secret_name])?;
+    if affected_rows > 0 {
+        log_operation(
+            AuditOperation::Restore,
+            Some(sec_type),
+            Some(secret_name),
+            None,
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+/// Trashed secrets keep their name so a new secret cannot take it until they are purged.
+pub fn check_secret_in_trash(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<bool, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT EXISTS(SELECT 1 FROM {} WHERE {} = ?1 AND deleted_at IS NOT NULL);",
+        t.table, t.name_col
+    );
+    let result = dbcon.query_row(&query, [secret_name], |row| row.get::<_, i32>(0))?;
+    Ok(result != 0)
+}
+
+/// Returns the trashed secrets of a type, oldest first. With `older_than` set to a number of
+/// seconds only secrets trashed at least that long ago are returned.
+pub fn get_trashed_secrets(
+    sec_type: SecretType,
+    older_than: Option<u64>,
+    dbcon: &Connection,
+) -> Result<Vec<TrashEntry>, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT {}, deleted_at FROM {} WHERE deleted_at IS NOT NULL \
+        AND deleted_at <= datetime('now', ?1) ORDER BY deleted_at;",
+        t.name_col, t.table
+    );
+    let modifier = format!("-{} seconds", older_than.unwrap_or(0));
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([modifier], |row| {
+        Ok(TrashEntry {
+            name: row.get(0)?,
+            secret_type: t.kind.to_string(),
+            deleted_at: row.get(1)?,
+        })
+    })?;
+
+    let mut results: Vec<TrashEntry> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result);
+    }
+    Ok(results)
+}
 
 pub fn check_account_exists(
     account_name: &str,
     dbcon: &Connection,
 ) -> Result<bool, rusqlite::Error> {
-    let query = "SELECT EXISTS(SELECT 1 FROM account WHERE acc_name = ?1);";
+    let query = "SELECT EXISTS(SELECT 1 FROM account WHERE acc_name = ?1 AND deleted_at IS NULL);";
 
     let result = dbcon.query_row(query, (account_name,), |row| row.get::<_, i32>(0))?;
     if result == 0 {
@@ -19,7 +394,7 @@ pub fn check_account_exists(
 }
 
 pub fn check_apikey_exists(apikey_name: &str, dbcon: &Connection) -> Result<bool, rusqlite::Error> {
-    let query = "SELECT EXISTS(SELECT 1 FROM api_keys WHERE api_name = ?1);";
+    let query = "SELECT EXISTS(SELECT 1 FROM api_keys WHERE api_name = ?1 AND deleted_at IS NULL);";
 
     let result = dbcon.query_row(query, (apikey_name,), |row| row.get::<_, i32>(0))?;
     if result == 0 {
@@ -33,23 +408,59 @@ pub fn add_account_to_db(
     account: &AccountObj,
     dbcon: &Connection,
 ) -> Result<usize, rusqlite::Error> {
-    let query = "INSERT INTO account(acc_name, user_name, password) VALUES (?1, ?2, ?3);";
-    let mut stmt = dbcon.prepare(query)?;
-    let affected_rows =
-        stmt.execute([&account.account_name, &account.user_name, &account.password])?;
+    let query = "INSERT INTO account(acc_name, user_name, password, otp, notes, created_at, updated_at) \
+        VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'), datetime('now'));";
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(
+        query,
+        (
+            &account.account_name,
+            &account.user_name,
+            &account.password,
+            &account.otp,
+            &account.notes,
+        ),
+    )?;
+    add_custom_fields(
+        SecretType::Login,
+        &account.account_name,
+        &account.custom_fields,
+        &tx,
+    )?;
+    log_operation(
+        AuditOperation::Add,
+        Some(SecretType::Login),
+        Some(&account.account_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
 
 pub fn add_apikey_to_db(api: &APIObj, dbcon: &Connection) -> Result<usize, rusqlite::Error> {
-    let query =
-        "INSERT INTO api_keys(api_name, description, user_name, api_key) VALUES (?1, ?2, ?3, ?4);";
-    let mut stmt = dbcon.prepare(query)?;
-    let affected_rows = stmt.execute([
-        &api.api_name,
-        &api.description,
-        &api.user_name,
-        &api.api_key,
-    ])?;
+    let query = "INSERT INTO api_keys(api_name, description, user_name, api_key, notes, created_at, updated_at) \
+        VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'), datetime('now'));";
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(
+        query,
+        (
+            &api.api_name,
+            &api.description,
+            &api.user_name,
+            &api.api_key,
+            &api.notes,
+        ),
+    )?;
+    add_custom_fields(SecretType::Api, &api.api_name, &api.custom_fields, &tx)?;
+    log_operation(
+        AuditOperation::Add,
+        Some(SecretType::Api),
+        Some(&api.api_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
 
@@ -57,9 +468,18 @@ pub fn delete_account_from_db(
     account_name: &str,
     dbcon: &Connection,
 ) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    delete_secret_references(SecretType::Login, account_name, &tx)?;
     let query = "DELETE FROM account WHERE acc_name = ?1;";
-    let mut stmt = dbcon.prepare(query)?;
-    let affected_rows = stmt.execute([account_name])?;
+    let affected_rows = tx.execute(query, [account_name])?;
+    log_operation(
+        AuditOperation::Delete,
+        Some(SecretType::Login),
+        Some(account_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
 
@@ -67,60 +487,60 @@ pub fn delete_apikey_from_db(
     apikey_name: &str,
     dbcon: &Connection,
 ) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    delete_secret_references(SecretType::Api, apikey_name, &tx)?;
     let query = "DELETE FROM api_keys WHERE api_name = ?1;";
-    let mut stmt = dbcon.prepare(query)?;
-    let affected_rows = stmt.execute([apikey_name])?;
+    let affected_rows = tx.execute(query, [apikey_name])?;
+    log_operation(
+        AuditOperation::Delete,
+        Some(SecretType::Api),
+        Some(apikey_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
 
 pub fn get_account_from_db(account_name: &str, dbcon: &Connection) -> Result<Secret, CMError> {
-    let query = "SELECT acc_name, user_name, password FROM account WHERE acc_name = ?1;";
-    let mut stmt = dbcon.prepare(query)?;
+    let query = format!(
+        "SELECT {} FROM account WHERE acc_name = ?1 AND deleted_at IS NULL;",
+        account_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
     let mut results = stmt.query([account_name])?;
     let result = results.next()?;
 
     if let Some(row) = result {
-        Ok(AccountObj {
-            account_name: row.get(0)?,
-            user_name: row.get(1)?,
-            password: row.get(2)?,
-        }
-        .into())
+        Ok(account_from_row(row)?.into())
     } else {
         Err(CustomError::new(&format!("Account {} not found", account_name)).into())
     }
 }
 
 pub fn get_apikey_from_db(apikey_name: &str, dbcon: &Connection) -> Result<Secret, CMError> {
-    let query =
-        "SELECT api_name, description, user_name, api_key FROM api_keys WHERE api_name = ?1;";
-    let mut stmt = dbcon.prepare(query)?;
+    let query = format!(
+        "SELECT {} FROM api_keys WHERE api_name = ?1 AND deleted_at IS NULL;",
+        apikey_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
     let mut results = stmt.query([apikey_name])?;
     let result = results.next()?;
 
     if let Some(row) = result {
-        Ok(APIObj {
-            api_name: row.get(0)?,
-            description: row.get(1)?,
-            user_name: row.get(2)?,
-            api_key: row.get(3)?,
-        }
-        .into())
+        Ok(apikey_from_row(row)?.into())
     } else {
         Err(CustomError::new(&format!("API Key {} not found", apikey_name)).into())
     }
 }
 
 pub fn get_all_accounts_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusqlite::Error> {
-    let query = "SELECT acc_name, user_name, password FROM account;";
-    let mut stmt = dbcon.prepare(query)?;
-    let rows = stmt.query_map([], |row| {
-        Ok(AccountObj {
-            account_name: row.get(0)?,
-            user_name: row.get(1)?,
-            password: row.get(2)?,
-        })
-    })?;
+    let query = format!(
+        "SELECT {} FROM account WHERE deleted_at IS NULL;",
+        account_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([], account_from_row)?;
 
     let mut results: Vec<Secret> = Vec::new();
 
@@ -131,16 +551,176 @@ pub fn get_all_accounts_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusql
 }
 
 pub fn get_all_apikeys_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusqlite::Error> {
-    let query = "SELECT api_name, description, user_name, api_key FROM api_keys;";
-    let mut stmt = dbcon.prepare(query)?;
-    let rows = stmt.query_map([], |row| {
-        Ok(APIObj {
-            api_name: row.get(0)?,
-            description: row.get(1)?,
-            user_name: row.get(2)?,
-            api_key: row.get(3)?,
-        })
-    })?;
+    let query = format!(
+        "SELECT {} FROM api_keys WHERE deleted_at IS NULL;",
+        apikey_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([], apikey_from_row)?;
+
+    let mut results: Vec<Secret> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result.into());
+    }
+    Ok(results)
+}
+
+pub fn check_ssh_key_exists(ssh_name: &str, dbcon: &Connection) -> Result<bool, rusqlite::Error> {
+    let query = "SELECT EXISTS(SELECT 1 FROM ssh_keys WHERE ssh_name = ?1 AND deleted_at IS NULL);";
+
+    let result = dbcon.query_row(query, (ssh_name,), |row| row.get::<_, i32>(0))?;
+    if result == 0 {
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+pub fn add_ssh_key_to_db(ssh: &SshObj, dbcon: &Connection) -> Result<usize, rusqlite::Error> {
+    let query = "INSERT INTO ssh_keys(ssh_name, comment, public_key, private_key, passphrase, notes, created_at, updated_at) \
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'), datetime('now'));";
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(
+        query,
+        (
+            &ssh.ssh_name,
+            &ssh.comment,
+            &ssh.public_key,
+            &ssh.private_key,
+            &ssh.passphrase,
+            &ssh.notes,
+        ),
+    )?;
+    add_custom_fields(SecretType::Ssh, &ssh.ssh_name, &ssh.custom_fields, &tx)?;
+    log_operation(
+        AuditOperation::Add,
+        Some(SecretType::Ssh),
+        Some(&ssh.ssh_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn delete_ssh_key_from_db(
+    ssh_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    delete_secret_references(SecretType::Ssh, ssh_name, &tx)?;
+    let query = "DELETE FROM ssh_keys WHERE ssh_name = ?1;";
+    let affected_rows = tx.execute(query, [ssh_name])?;
+    log_operation(
+        AuditOperation::Delete,
+        Some(SecretType::Ssh),
+        Some(ssh_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn get_ssh_key_from_db(ssh_name: &str, dbcon: &Connection) -> Result<Secret, CMError> {
+    let query = format!(
+        "SELECT {} FROM ssh_keys WHERE ssh_name = ?1 AND deleted_at IS NULL;",
+        ssh_key_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let mut results = stmt.query([ssh_name])?;
+    let result = results.next()?;
+
+    if let Some(row) = result {
+        Ok(ssh_key_from_row(row)?.into())
+    } else {
+        Err(CustomError::new(&format!("SSH Key {} not found", ssh_name)).into())
+    }
+}
+
+pub fn get_all_ssh_keys_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusqlite::Error> {
+    let query = format!(
+        "SELECT {} FROM ssh_keys WHERE deleted_at IS NULL;",
+        ssh_key_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([], ssh_key_from_row)?;
+
+    let mut results: Vec<Secret> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result.into());
+    }
+    Ok(results)
+}
+
+pub fn check_note_exists(note_name: &str, dbcon: &Connection) -> Result<bool, rusqlite::Error> {
+    let query =
+        "SELECT EXISTS(SELECT 1 FROM secure_notes WHERE note_name = ?1 AND deleted_at IS NULL);";
+
+    let result = dbcon.query_row(query, (note_name,), |row| row.get::<_, i32>(0))?;
+    if result == 0 {
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+pub fn add_note_to_db(note: &NoteObj, dbcon: &Connection) -> Result<usize, rusqlite::Error> {
+    let query = "INSERT INTO secure_notes(note_name, body, notes, created_at, updated_at) \
+        VALUES (?1, ?2, ?3, datetime('now'), datetime('now'));";
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(query, (&note.note_name, &note.body, &note.notes))?;
+    add_custom_fields(SecretType::Note, &note.note_name, &note.custom_fields, &tx)?;
+    log_operation(
+        AuditOperation::Add,
+        Some(SecretType::Note),
+        Some(&note.note_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn delete_note_from_db(note_name: &str, dbcon: &Connection) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    delete_secret_references(SecretType::Note, note_name, &tx)?;
+    let query = "DELETE FROM secure_notes WHERE note_name = ?1;";
+    let affected_rows = tx.execute(query, [note_name])?;
+    log_operation(
+        AuditOperation::Delete,
+        Some(SecretType::Note),
+        Some(note_name),
+        None,
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn get_note_from_db(note_name: &str, dbcon: &Connection) -> Result<Secret, CMError> {
+    let query = format!(
+        "SELECT {} FROM secure_notes WHERE note_name = ?1 AND deleted_at IS NULL;",
+        note_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let mut results = stmt.query([note_name])?;
+    let result = results.next()?;
+
+    if let Some(row) = result {
+        Ok(note_from_row(row)?.into())
+    } else {
+        Err(CustomError::new(&format!("Note {} not found", note_name)).into())
+    }
+}
+
+pub fn get_all_notes_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusqlite::Error> {
+    let query = format!(
+        "SELECT {} FROM secure_notes WHERE deleted_at IS NULL;",
+        note_columns()
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([], note_from_row)?;
 
     let mut results: Vec<Secret> = Vec::new();
     for result in rows.flatten() {
@@ -151,7 +731,7 @@ pub fn get_all_apikeys_from_db(dbcon: &Connection) -> Result<Vec<Secret>, rusqli
 
 pub fn change_db_account_field(
     account_name: &str,
-    field: FieldType,
+    field: &FieldType,
     new_value: &str,
     dbcon: &Connection,
 ) -> Result<usize, CMError> {
@@ -159,6 +739,8 @@ pub fn change_db_account_field(
         FieldType::User => "user_name",
         FieldType::Secname => "acc_name",
         FieldType::Pass => "password",
+        FieldType::Otp => "otp",
+        FieldType::Notes => "notes",
         _ => {
             return Err(
                 CustomError::new("The given field is invalid for a login credential").into(),
@@ -166,15 +748,26 @@ pub fn change_db_account_field(
         }
     };
 
-    let query = format! { "UPDATE account SET {} = ?1 WHERE acc_name = ?2;", field_to_change};
-    let mut stmt = dbcon.prepare(&query)?;
-    let affected_rows = stmt.execute([new_value, account_name])?;
+    let tx = dbcon.unchecked_transaction()?;
+    if *field == FieldType::Pass {
+        save_to_history(SecretType::Login, account_name, "password", &tx)?;
+    }
+    let query = format! { "UPDATE account SET {} = ?1, updated_at = datetime('now') WHERE acc_name = ?2;", field_to_change};
+    let affected_rows = tx.execute(&query, [new_value, account_name])?;
+    log_operation(
+        AuditOperation::Change,
+        Some(SecretType::Login),
+        Some(account_name),
+        Some(&field.name()),
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
 
 pub fn change_db_apikey_field(
     api_name: &str,
-    field: FieldType,
+    field: &FieldType,
     new_value: &str,
     dbcon: &Connection,
 ) -> Result<usize, CMError> {
@@ -183,16 +776,693 @@ pub fn change_db_apikey_field(
         FieldType::Secname => "api_name",
         FieldType::Desc => "description",
         FieldType::Key => "api_key",
+        FieldType::Notes => "notes",
         _ => {
             return Err(CustomError::new("The given field is invalid for an api key.").into());
         }
     };
 
+    let tx = dbcon.unchecked_transaction()?;
+    if *field == FieldType::Key {
+        save_to_history(SecretType::Api, api_name, "api_key", &tx)?;
+    }
+    let query = format!(
+        "UPDATE api_keys SET {} = ?1, updated_at = datetime('now') WHERE api_name = ?2;",
+        field_to_change
+    );
+    let affected_rows = tx.execute(&query, [new_value, api_name])?;
+    log_operation(
+        AuditOperation::Change,
+        Some(SecretType::Api),
+        Some(api_name),
+        Some(&field.name()),
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn change_db_ssh_key_field(
+    ssh_name: &str,
+    field: &FieldType,
+    new_value: &str,
+    dbcon: &Connection,
+) -> Result<usize, CMError> {
+    let field_to_change = match field {
+        FieldType::Secname => "ssh_name",
+        FieldType::Comment => "comment",
+        FieldType::Notes => "notes",
+        _ => {
+            return Err(CustomError::new("The given field is invalid for an ssh key.").into());
+        }
+    };
+
+    let query = format!(
+        "UPDATE ssh_keys SET {} = ?1, updated_at = datetime('now') WHERE ssh_name = ?2;",
+        field_to_change
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, [new_value, ssh_name])?;
+    log_operation(
+        AuditOperation::Change,
+        Some(SecretType::Ssh),
+        Some(ssh_name),
+        Some(&field.name()),
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn change_db_note_field(
+    note_name: &str,
+    field: &FieldType,
+    new_value: &str,
+    dbcon: &Connection,
+) -> Result<usize, CMError> {
+    let field_to_change = match field {
+        FieldType::Secname => "note_name",
+        FieldType::Body => "body",
+        FieldType::Notes => "notes",
+        _ => {
+            return Err(CustomError::new("The given field is invalid for a note.").into());
+        }
+    };
+
+    let tx = dbcon.unchecked_transaction()?;
+    if *field == FieldType::Body {
+        save_to_history(SecretType::Note, note_name, "body", &tx)?;
+    }
     let query = format!(
-        "UPDATE api_keys SET {} = ?1 WHERE api_name = ?2;",
+        "UPDATE secure_notes SET {} = ?1, updated_at = datetime('now') WHERE note_name = ?2;",
         field_to_change
     );
+    let affected_rows = tx.execute(&query, [new_value, note_name])?;
+    log_operation(
+        AuditOperation::Change,
+        Some(SecretType::Note),
+        Some(note_name),
+        Some(&field.name()),
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+/// Replaces the key pair of an ssh key. The previous private key is kept in the history.
+pub fn change_db_ssh_keypair(
+    ssh_name: &str,
+    private_key: &SecretString,
+    public_key: &str,
+    passphrase: &SecretString,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    save_to_history(SecretType::Ssh, ssh_name, "private_key", &tx)?;
+    let query = "UPDATE ssh_keys SET private_key = ?1, public_key = ?2, passphrase = ?3, \
+        updated_at = datetime('now') WHERE ssh_name = ?4;";
+    let affected_rows = tx.execute(query, (private_key, public_key, passphrase, ssh_name))?;
+    log_operation(
+        AuditOperation::Change,
+        Some(SecretType::Ssh),
+        Some(ssh_name),
+        Some("privkey"),
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+/// Records that the secret has just been read.
+pub fn mark_secret_accessed(secret: &Secret, dbcon: &Connection) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(secret.get_type());
+    let query = format!(
+        "UPDATE {} SET accessed_at = datetime('now') WHERE {} = ?1;",
+        t.table, t.name_col
+    );
+    let affected_rows = dbcon.execute(&query, [secret.get_name()])?;
+    Ok(affected_rows)
+}
+
+/// Copies the current value of `value_col` into secret_history before it gets overwritten.
+fn save_to_history(
+    sec_type: SecretType,
+    secret_name: &str,
+    value_col: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "INSERT INTO secret_history(secret_type, secret_id, value, replaced_at) \
+        SELECT ?1, {}, {}, datetime('now') FROM {} WHERE {} = ?2;",
+        t.id_col, value_col, t.table, t.name_col
+    );
+    dbcon.execute(&query, [t.kind, secret_name])
+}
+
+/// Removes the rows in the tables shared by all secret types that belong to a secret.
+fn delete_secret_references(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<(), rusqlite::Error> {
+    let t = secret_table(sec_type);
+    for shared_table in [
+        "secret_history",
+        "secret_tags",
+        "custom_fields",
+        "attachments",
+    ] {
+        let query = format!(
+            "DELETE FROM {} WHERE secret_type = ?1 AND secret_id = \
+            (SELECT {} FROM {} WHERE {} = ?2);",
+            shared_table, t.id_col, t.table, t.name_col
+        );
+        dbcon.execute(&query, [t.kind, secret_name])?;
+    }
+    delete_unused_tags(dbcon)?;
+    Ok(())
+}
+
+/// Returns the previous passwords or api keys of a secret, most recently replaced first.
+pub fn get_secret_history(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<Vec<HistoryEntry>, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT h.value, h.replaced_at FROM secret_history h JOIN {} s ON s.{} = h.secret_id \
+        WHERE h.secret_type = ?1 AND s.{} = ?2 ORDER BY h.hist_id DESC;",
+        t.table, t.id_col, t.name_col
+    );
     let mut stmt = dbcon.prepare(&query)?;
-    let affected_rows = stmt.execute([new_value, api_name])?;
+    let rows = stmt.query_map([t.kind, secret_name], |row| {
+        Ok(HistoryEntry {
+            value: row.get(0)?,
+            replaced_at: row.get(1)?,
+        })
+    })?;
+
+    let mut results: Vec<HistoryEntry> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result);
+    }
+    Ok(results)
+}
+
+pub fn add_tags_to_secret(
+    sec_type: SecretType,
+    secret_name: &str,
+    tags: &[String],
+    dbcon: &Connection,
+) -> Result<(), rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "INSERT OR IGNORE INTO secret_tags(secret_type, secret_id, tag_id) \
+        SELECT ?1, s.{}, t.tag_id FROM {} s, tags t WHERE s.{} = ?2 AND t.tag_name = ?3;",
+        t.id_col, t.table, t.name_col
+    );
+
+    let tx = dbcon.unchecked_transaction()?;
+    for tag in tags {
+        tx.execute("INSERT OR IGNORE INTO tags(tag_name) VALUES (?1);", [tag])?;
+        tx.execute(&query, [t.kind, secret_name, tag])?;
+    }
+    if !tags.is_empty() {
+        log_operation(
+            AuditOperation::Change,
+            Some(sec_type),
+            Some(secret_name),
+            Some("tags"),
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn remove_tags_from_secret(
+    sec_type: SecretType,
+    secret_name: &str,
+    tags: &[String],
+    dbcon: &Connection,
+) -> Result<(), rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "DELETE FROM secret_tags WHERE secret_type = ?1 \
+        AND secret_id = (SELECT {} FROM {} WHERE {} = ?2) \
+        AND tag_id = (SELECT tag_id FROM tags WHERE tag_name = ?3);",
+        t.id_col, t.table, t.name_col
+    );
+
+    let tx = dbcon.unchecked_transaction()?;
+    for tag in tags {
+        tx.execute(&query, [t.kind, secret_name, tag])?;
+    }
+    delete_unused_tags(&tx)?;
+    if !tags.is_empty() {
+        log_operation(
+            AuditOperation::Change,
+            Some(sec_type),
+            Some(secret_name),
+            Some("tags"),
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn delete_unused_tags(dbcon: &Connection) -> Result<usize, rusqlite::Error> {
+    let query = "DELETE FROM tags WHERE tag_id NOT IN (SELECT tag_id FROM secret_tags);";
+    dbcon.execute(query, [])
+}
+
+fn add_custom_fields(
+    sec_type: SecretType,
+    secret_name: &str,
+    fields: &[CustomField],
+    dbcon: &Connection,
+) -> Result<(), rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "INSERT OR REPLACE INTO custom_fields(secret_type, secret_id, field_key, field_value, sensitive) \
+        SELECT ?1, {}, ?2, ?3, ?4 FROM {} WHERE {} = ?5;",
+        t.id_col, t.table, t.name_col
+    );
+    for field in fields {
+        dbcon.execute(
+            &query,
+            (
+                t.kind,
+                &field.key,
+                &field.value,
+                field.sensitive,
+                secret_name,
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Adds a custom field to a secret or replaces the value of an existing one.
+pub fn set_custom_field(
+    sec_type: SecretType,
+    secret_name: &str,
+    field: &CustomField,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    add_custom_fields(sec_type, secret_name, std::slice::from_ref(field), &tx)?;
+    let affected_rows = touch_secret(&secret_table(sec_type), secret_name, &tx)?;
+    log_operation(
+        AuditOperation::Change,
+        Some(sec_type),
+        Some(secret_name),
+        Some(&format!("custom:{}", field.key)),
+        &tx,
+    )?;
+    tx.commit()?;
     Ok(affected_rows)
 }
+
+/// Whether the secret already has a custom field named key that is marked sensitive.
+pub fn custom_field_is_sensitive(
+    sec_type: SecretType,
+    secret_name: &str,
+    key: &str,
+    dbcon: &Connection,
+) -> Result<bool, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT EXISTS(SELECT 1 FROM custom_fields WHERE secret_type = ?1 AND field_key = ?2 \
+        AND sensitive = 1 AND secret_id = (SELECT {} FROM {} WHERE {} = ?3));",
+        t.id_col, t.table, t.name_col
+    );
+    let result = dbcon.query_row(&query, [t.kind, key, secret_name], |row| {
+        row.get::<_, i32>(0)
+    })?;
+    Ok(result != 0)
+}
+
+pub fn remove_custom_field(
+    sec_type: SecretType,
+    secret_name: &str,
+    key: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "DELETE FROM custom_fields WHERE secret_type = ?1 AND field_key = ?2 \
+        AND secret_id = (SELECT {} FROM {} WHERE {} = ?3);",
+        t.id_col, t.table, t.name_col
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, [t.kind, key, secret_name])?;
+    if affected_rows > 0 {
+        touch_secret(&t, secret_name, &tx)?;
+        log_operation(
+            AuditOperation::Change,
+            Some(sec_type),
+            Some(secret_name),
+            Some(&format!("custom:{}", key)),
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+pub fn check_attachment_exists(
+    sec_type: SecretType,
+    secret_name: &str,
+    file_name: &str,
+    dbcon: &Connection,
+) -> Result<bool, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT EXISTS(SELECT 1 FROM attachments WHERE secret_type = ?1 AND file_name = ?2 \
+        AND secret_id = (SELECT {} FROM {} WHERE {} = ?3));",
+        t.id_col, t.table, t.name_col
+    );
+
+    let result = dbcon.query_row(&query, [t.kind, file_name, secret_name], |row| {
+        row.get::<_, i32>(0)
+    })?;
+    Ok(result != 0)
+}
+
+pub fn add_attachment_to_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    file_name: &str,
+    data: &[u8],
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "INSERT INTO attachments(secret_type, secret_id, file_name, data, added_at) \
+        SELECT ?1, {}, ?2, ?3, datetime('now') FROM {} WHERE {} = ?4;",
+        t.id_col, t.table, t.name_col
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, (t.kind, file_name, data, secret_name))?;
+    touch_secret(&t, secret_name, &tx)?;
+    log_operation(
+        AuditOperation::Change,
+        Some(sec_type),
+        Some(secret_name),
+        Some(&format!("attachment:{}", file_name)),
+        &tx,
+    )?;
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+/// Lists the attachments of a secret without reading their contents.
+pub fn get_attachments_from_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<Vec<Attachment>, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT a.file_name, length(a.data), a.added_at FROM attachments a \
+        JOIN {} s ON s.{} = a.secret_id WHERE a.secret_type = ?1 AND s.{} = ?2 \
+        ORDER BY a.file_name;",
+        t.table, t.id_col, t.name_col
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map([t.kind, secret_name], |row| {
+        Ok(Attachment {
+            file_name: row.get(0)?,
+            size: row.get(1)?,
+            added_at: row.get(2)?,
+        })
+    })?;
+
+    let mut results: Vec<Attachment> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result);
+    }
+    Ok(results)
+}
+
+pub fn get_attachment_data_from_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    file_name: &str,
+    dbcon: &Connection,
+) -> Result<Vec<u8>, CMError> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "SELECT a.data FROM attachments a JOIN {} s ON s.{} = a.secret_id \
+        WHERE a.secret_type = ?1 AND s.{} = ?2 AND a.file_name = ?3;",
+        t.table, t.id_col, t.name_col
+    );
+    let mut stmt = dbcon.prepare(&query)?;
+    let mut results = stmt.query([t.kind, secret_name, file_name])?;
+
+    if let Some(row) = results.next()? {
+        Ok(row.get(0)?)
+    } else {
+        Err(CustomError::new(&format!(
+            "{} has no attachment named {}",
+            secret_name, file_name
+        ))
+        .into())
+    }
+}
+
+pub fn remove_attachment_from_db(
+    sec_type: SecretType,
+    secret_name: &str,
+    file_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let t = secret_table(sec_type);
+    let query = format!(
+        "DELETE FROM attachments WHERE secret_type = ?1 AND file_name = ?2 \
+        AND secret_id = (SELECT {} FROM {} WHERE {} = ?3);",
+        t.id_col, t.table, t.name_col
+    );
+    let tx = dbcon.unchecked_transaction()?;
+    let affected_rows = tx.execute(&query, [t.kind, file_name, secret_name])?;
+    if affected_rows > 0 {
+        touch_secret(&t, secret_name, &tx)?;
+        log_operation(
+            AuditOperation::Change,
+            Some(sec_type),
+            Some(secret_name),
+            Some(&format!("attachment:{}", file_name)),
+            &tx,
+        )?;
+    }
+    tx.commit()?;
+    Ok(affected_rows)
+}
+
+/// Records that the secret has just been changed.
+fn touch_secret(
+    t: &SecretTable,
+    secret_name: &str,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let query = format!(
+        "UPDATE {} SET updated_at = datetime('now') WHERE {} = ?1;",
+        t.table, t.name_col
+    );
+    dbcon.execute(&query, [secret_name])
+}
+
+/// The user the operations are logged for, taken from the environment.
+fn current_actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or("unknown".to_string())
+}
+
+/// Appends an entry to the audit log. Only names are recorded, never secret values.
+pub fn log_operation(
+    operation: AuditOperation,
+    sec_type: Option<SecretType>,
+    secret_name: Option<&str>,
+    field: Option<&str>,
+    dbcon: &Connection,
+) -> Result<usize, rusqlite::Error> {
+    let query = "INSERT INTO audit_log(logged_at, actor, operation, secret_type, secret_name, field, unlocked_with) \
+        VALUES (datetime('now'), ?1, ?2, ?3, ?4, ?5, ?6);";
+    let kind = sec_type.map(|t| secret_table(t).kind);
+    let unlocked_with = key::unlock_source().map(|s| s.as_str());
+    dbcon.execute(
+        query,
+        (
+            current_actor(),
+            operation.as_str(),
+            kind,
+            secret_name,
+            field,
+            unlocked_with,
+        ),
+    )
+}
+
+/// Filters for reading the audit log. Dates are compared as UTC timestamps.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub secret_name: Option<String>,
+    pub sec_type: Option<SecretType>,
+    pub operation: Option<AuditOperation>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Returns the audit log entries matching the filter, oldest first. With a limit only the most
+/// recent entries are returned.
+pub fn get_audit_log(
+    filter: &AuditFilter,
+    dbcon: &Connection,
+) -> Result<Vec<AuditEntry>, rusqlite::Error> {
+    let mut conditions: Vec<&str> = Vec::new();
+    let mut params: Vec<String> = Vec::new();
+    if let Some(name) = &filter.secret_name {
+        conditions.push("secret_name = ?");
+        params.push(name.clone());
+    }
+    if let Some(t) = filter.sec_type {
+        conditions.push("secret_type = ?");
+        params.push(secret_table(t).kind.to_string());
+    }
+    if let Some(op) = filter.operation {
+        conditions.push("operation = ?");
+        params.push(op.as_str().to_string());
+    }
+    if let Some(since) = &filter.since {
+        conditions.push("logged_at >= ?");
+        params.push(since.clone());
+    }
+    if let Some(until) = &filter.until {
+        conditions.push("logged_at <= ?");
+        params.push(until.clone());
+    }
+
+    let mut query = "SELECT logged_at, actor, operation, secret_type, secret_name, field, \
+        unlocked_with FROM audit_log"
+        .to_string();
+    if !conditions.is_empty() {
+        query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    query.push_str(" ORDER BY log_id DESC");
+    if let Some(limit) = filter.limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    let mut stmt = dbcon.prepare(&query)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+        Ok(AuditEntry {
+            logged_at: row.get(0)?,
+            actor: row.get(1)?,
+            operation: row.get(2)?,
+            secret_type: row.get(3)?,
+            secret_name: row.get(4)?,
+            field: row.get(5)?,
+            unlocked_with: row.get(6)?,
+        })
+    })?;
+
+    let mut results: Vec<AuditEntry> = Vec::new();
+    for result in rows.flatten() {
+        results.push(result);
+    }
+    results.reverse();
+    Ok(results)
+}
+
+/// The newest audit log entry of an operation that can change what the search index holds.
+fn last_write_log_id(dbcon: &Connection) -> Result<i64, rusqlite::Error> {
+    dbcon.query_row(
+        "SELECT COALESCE(MAX(log_id), 0) FROM audit_log \
+        WHERE operation NOT IN ('get', 'pull', 'master', 'rekdf');",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Whether the vault has been written to since the search index was last built. Every write is
+/// recorded in the audit log so the index only has to remember the last entry it has seen.
+pub fn search_index_is_stale(dbcon: &Connection) -> Result<bool, rusqlite::Error> {
+    let indexed: Option<i64> = dbcon
+        .query_row(
+            "SELECT indexed_log_id FROM search_index_state;",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(indexed != Some(last_write_log_id(dbcon)?))
+}
+
+/// Replaces the contents of the search index with the searchable fields of the given secrets.
+pub fn rebuild_search_index(secrets: &[Secret], dbcon: &Connection) -> Result<(), rusqlite::Error> {
+    let tx = dbcon.unchecked_transaction()?;
+    tx.execute("DELETE FROM search_index;", [])?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO search_index(secret_type, secret_name, content) VALUES (?1, ?2, ?3);",
+        )?;
+        for secret in secrets {
+            let fields = secret.search_fields();
+            let values: Vec<&str> = fields.iter().map(|(_, value)| value.expose()).collect();
+            let content = SecretString::new(values.join("\n"));
+            stmt.execute((
+                secret_table(secret.get_type()).kind,
+                secret.get_name(),
+                &content,
+            ))?;
+        }
+    }
+    tx.execute("DELETE FROM search_index_state;", [])?;
+    tx.execute(
+        "INSERT INTO search_index_state(indexed_log_id) VALUES (?1);",
+        [last_write_log_id(&tx)?],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Looks up secrets in the search index, best match first. Every word of the query has to match
+/// the start of a word in the secret.
+pub fn search_index(
+    query: &str,
+    sec_type: Option<SecretType>,
+    dbcon: &Connection,
+) -> Result<Vec<(SecretType, String)>, rusqlite::Error> {
+    // each word is quoted so that characters like - and : are not read as fts5 operators.
+    let fts_query: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    let mut stmt = dbcon.prepare(
+        "SELECT secret_type, secret_name FROM search_index \
+        WHERE search_index MATCH ?1 ORDER BY rank;",
+    )?;
+    let rows = stmt.query_map([fts_query.join(" ")], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut results = Vec::new();
+    for (kind, name) in rows.flatten() {
+        let found_type = SecretType::value_variants()
+            .iter()
+            .find(|t| secret_table(**t).kind == kind);
+        if let Some(t) = found_type
+            && sec_type.is_none_or(|s| s == *t)
+        {
+            results.push((*t, name));
+        }
+    }
+    Ok(results)
+}
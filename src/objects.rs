@@ -1,32 +1,158 @@
-use crate::util::argparser::FieldType;
+use crate::util::argparser::{FieldType, SecretType};
+use crate::util::clipboard::copy_to_clipboard;
 use crate::util::errors::CMError;
 use crate::util::ioutils::print_result;
-use std::thread::sleep;
+use crate::util::secret::SecretString;
 
-use arboard::Clipboard;
 use serde::{Deserialize, Serialize};
 
-use std::{fmt::Display, time};
+use std::fmt::Display;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Secret {
     Account(AccountObj),
     API(APIObj),
+    Ssh(SshObj),
+    Note(NoteObj),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AccountObj {
     pub account_name: String,
     pub user_name: String,
-    pub password: String,
+    pub password: SecretString,
+    /// The otpauth:// URI of the two factor authentication seed, empty if there is none.
+    #[serde(default)]
+    pub otp: SecretString,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub accessed_at: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct APIObj {
     pub api_name: String,
     pub description: String,
     pub user_name: String,
-    pub api_key: String,
+    pub api_key: SecretString,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub accessed_at: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+}
+
+/// An ssh key pair. public_key holds the key type and base64 key data without a comment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SshObj {
+    pub ssh_name: String,
+    pub comment: String,
+    pub public_key: String,
+    pub private_key: SecretString,
+    pub passphrase: SecretString,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub accessed_at: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+}
+
+/// A multi-line note such as a certificate or a sheet of recovery codes. The body is kept and
+/// printed exactly as it was entered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NoteObj {
+    pub note_name: String,
+    pub body: SecretString,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub accessed_at: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+}
+
+/// A user defined key/value pair stored with a secret. Sensitive values are masked when the whole
+/// secret is printed and only shown when the field is requested explicitly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomField {
+    pub key: String,
+    pub value: SecretString,
+    pub sensitive: bool,
+}
+
+/// A file stored with a secret. Only the details are loaded, the contents are read when the file
+/// is extracted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_name: String,
+    pub size: i64,
+    pub added_at: String,
+}
+
+/// A secret that has been moved to the trash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub name: String,
+    pub secret_type: String,
+    pub deleted_at: String,
+}
+
+/// A recorded vault operation. Only the names of secrets and fields are logged, never values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub logged_at: String,
+    pub actor: String,
+    pub operation: String,
+    pub secret_type: Option<String>,
+    pub secret_name: Option<String>,
+    pub field: Option<String>,
+    /// How the master password was given: prompt, fd, stdin, env, askpass or agent.
+    #[serde(default)]
+    pub unlocked_with: Option<String>,
+}
+
+/// A secret found by cman search and the field that matched best.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub secret_type: String,
+    pub name: String,
+    pub field: String,
+    pub value: SecretString,
+    pub score: i64,
+}
+
+/// A vault registered in the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultEntry {
+    pub name: String,
+    pub path: String,
+    pub url: Option<String>,
+    pub keyfile: Option<String>,
+    pub default: bool,
+}
+
+/// A previous password or api key of a secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub value: SecretString,
+    pub replaced_at: String,
 }
 
 impl From<AccountObj> for Secret {
@@ -41,11 +167,25 @@ impl From<APIObj> for Secret {
     }
 }
 
+impl From<SshObj> for Secret {
+    fn from(value: SshObj) -> Self {
+        Secret::Ssh(value)
+    }
+}
+
+impl From<NoteObj> for Secret {
+    fn from(value: NoteObj) -> Self {
+        Secret::Note(value)
+    }
+}
+
 impl Display for Secret {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Account(acc) => write!(f, "{}", acc),
             Self::API(api) => write!(f, "{}", api),
+            Self::Ssh(ssh) => write!(f, "{}", ssh),
+            Self::Note(note) => write!(f, "{}", note),
         }
     }
 }
@@ -62,29 +202,93 @@ impl Display for APIObj {
     }
 }
 
+impl Display for SshObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ssh_name)
+    }
+}
+
+impl Display for NoteObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.note_name)
+    }
+}
+
+fn print_tags(tags: &[String]) {
+    if !tags.is_empty() {
+        print_result("Tags", &tags.join(", "));
+    }
+}
+
+fn print_notes_and_custom_fields(notes: &str, custom_fields: &[CustomField]) {
+    if !notes.is_empty() {
+        print_result("Notes", notes);
+    }
+    for field in custom_fields {
+        if field.sensitive {
+            print_result(&field.key, "********");
+        } else {
+            print_result(&field.key, field.value.expose());
+        }
+    }
+}
+
+fn find_custom_field<'a>(custom_fields: &'a [CustomField], key: &str) -> Option<&'a CustomField> {
+    custom_fields.iter().find(|f| f.key == key)
+}
+
+/// Timestamps are stored in UTC as returned by sqlite's datetime('now').
+fn print_timestamps(created: &Option<String>, updated: &Option<String>, accessed: &Option<String>) {
+    let format = |time: &Option<String>, missing: &str| match time {
+        Some(t) => format!("{} UTC", t),
+        None => missing.to_string(),
+    };
+    print_result("Created", &format(created, "unknown"));
+    print_result("Updated", &format(updated, "unknown"));
+    print_result("Accessed", &format(accessed, "never"));
+}
+
 impl AccountObj {
     fn print(&self) {
         print_result("Name", &self.account_name);
         print_result("User", &self.user_name);
-        print_result("Pass", &self.password);
+        print_result("Pass", self.password.expose());
+        if !self.otp.is_empty() {
+            print_result("OTP", self.otp.expose());
+        }
+        print_notes_and_custom_fields(&self.notes, &self.custom_fields);
+        print_tags(&self.tags);
+        print_timestamps(&self.created_at, &self.updated_at, &self.accessed_at);
         println!();
     }
 
-    fn print_field(&self, field: FieldType) {
+    fn print_field(&self, field: &FieldType) {
         match field {
             FieldType::User => print_result("User", &self.user_name),
             FieldType::Secname => print_result("Name", &self.account_name),
-            FieldType::Pass => print_result("Pass", &self.password),
+            FieldType::Pass => print_result("Pass", self.password.expose()),
+            FieldType::Otp => print_result("OTP", self.otp.expose()),
+            FieldType::Notes => print_result("Notes", &self.notes),
+            FieldType::Custom(key) => {
+                if let Some(f) = find_custom_field(&self.custom_fields, key) {
+                    print_result(&f.key, f.value.expose())
+                }
+            }
             _ => (),
         }
     }
 
-    fn get_field(&self, field: FieldType) -> String {
+    fn get_field(&self, field: &FieldType) -> SecretString {
         match field {
-            FieldType::User => self.user_name.clone(),
-            FieldType::Secname => self.account_name.clone(),
+            FieldType::User => self.user_name.as_str().into(),
+            FieldType::Secname => self.account_name.as_str().into(),
             FieldType::Pass => self.password.clone(),
-            _ => "".to_string(),
+            FieldType::Otp => self.otp.clone(),
+            FieldType::Notes => self.notes.as_str().into(),
+            FieldType::Custom(key) => find_custom_field(&self.custom_fields, key)
+                .map(|f| f.value.clone())
+                .unwrap_or_default(),
+            _ => SecretString::default(),
         }
     }
 
@@ -95,31 +299,25 @@ impl AccountObj {
         }
     }
 
-    fn get_field_json_str(&self, field: FieldType) -> String {
+    fn get_field_json_str(&self, field: &FieldType) -> String {
         let json_str = match field {
             FieldType::User => serde_json::json!({"User": self.user_name}),
             FieldType::Secname => serde_json::json!({"Name": self.account_name}),
-            FieldType::Pass => serde_json::json!({"Pass": self.password}),
+            FieldType::Pass => serde_json::json!({"Pass": &self.password}),
+            FieldType::Otp => serde_json::json!({"OTP": &self.otp}),
+            FieldType::Notes => serde_json::json!({"Notes": self.notes}),
+            FieldType::Custom(key) => match find_custom_field(&self.custom_fields, key) {
+                Some(f) => serde_json::json!({ &f.key: f.value }),
+                None => return "".to_string(),
+            },
             _ => return "".to_string(),
         };
 
         json_str.to_string()
     }
 
-    fn send_field_to_clipboard(&self, field: FieldType) -> Result<(), CMError> {
-        let data = match field {
-            FieldType::User => &self.user_name,
-            FieldType::Secname => &self.account_name,
-            FieldType::Pass => &self.password,
-            _ => "",
-        };
-
-        let mut clipboard = Clipboard::new()?;
-
-        clipboard.set_text(data)?;
-        // to stop clipboard from being dropped early which causes an error on linux
-        sleep(time::Duration::from_millis(2));
-        Ok(())
+    fn send_field_to_clipboard(&self, field: &FieldType) -> Result<(), CMError> {
+        copy_to_clipboard(self.get_field(field).expose())
     }
 }
 
@@ -128,27 +326,40 @@ impl APIObj {
         print_result("Name", &self.api_name);
         print_result("User", &self.user_name);
         print_result("Desc", &self.description);
-        print_result("Key", &self.api_key);
+        print_result("Key", self.api_key.expose());
+        print_notes_and_custom_fields(&self.notes, &self.custom_fields);
+        print_tags(&self.tags);
+        print_timestamps(&self.created_at, &self.updated_at, &self.accessed_at);
         println!();
     }
 
-    fn print_field(&self, field: FieldType) {
+    fn print_field(&self, field: &FieldType) {
         match field {
             FieldType::Secname => print_result("Name", &self.api_name),
             FieldType::Desc => print_result("Desc", &self.description),
             FieldType::User => print_result("User", &self.user_name),
-            FieldType::Key => print_result("Key", &self.api_key),
+            FieldType::Key => print_result("Key", self.api_key.expose()),
+            FieldType::Notes => print_result("Notes", &self.notes),
+            FieldType::Custom(key) => {
+                if let Some(f) = find_custom_field(&self.custom_fields, key) {
+                    print_result(&f.key, f.value.expose())
+                }
+            }
             _ => (),
         }
     }
 
-    fn get_field(&self, field: FieldType) -> String {
+    fn get_field(&self, field: &FieldType) -> SecretString {
         match field {
-            FieldType::Secname => self.api_name.clone(),
-            FieldType::Desc => self.description.clone(),
-            FieldType::User => self.user_name.clone(),
+            FieldType::Secname => self.api_name.as_str().into(),
+            FieldType::Desc => self.description.as_str().into(),
+            FieldType::User => self.user_name.as_str().into(),
             FieldType::Key => self.api_key.clone(),
-            _ => "".to_string(),
+            FieldType::Notes => self.notes.as_str().into(),
+            FieldType::Custom(key) => find_custom_field(&self.custom_fields, key)
+                .map(|f| f.value.clone())
+                .unwrap_or_default(),
+            _ => SecretString::default(),
         }
     }
     fn get_json_str(&self) -> String {
@@ -158,40 +369,185 @@ impl APIObj {
         }
     }
 
-    fn get_field_json_str(&self, field: FieldType) -> String {
+    fn get_field_json_str(&self, field: &FieldType) -> String {
         let json_str = match field {
             FieldType::Secname => serde_json::json!({"Name": &self.api_name}),
             FieldType::Desc => serde_json::json!({"Description": &self.description}),
             FieldType::User => serde_json::json!({"User": &self.user_name}),
             FieldType::Key => serde_json::json!({"Key": &self.api_key}),
+            FieldType::Notes => serde_json::json!({"Notes": &self.notes}),
+            FieldType::Custom(key) => match find_custom_field(&self.custom_fields, key) {
+                Some(f) => serde_json::json!({ &f.key: &f.value }),
+                None => return "".to_string(),
+            },
             _ => return "".to_string(),
         };
         json_str.to_string()
     }
 
-    fn send_field_to_clipboard(&self, field: FieldType) -> Result<(), CMError> {
-        let data = match field {
-            FieldType::Secname => &self.api_name,
-            FieldType::Desc => &self.description,
-            FieldType::User => &self.user_name,
-            FieldType::Key => &self.api_key,
-            _ => "",
+    fn send_field_to_clipboard(&self, field: &FieldType) -> Result<(), CMError> {
+        copy_to_clipboard(self.get_field(field).expose())
+    }
+}
+
+impl SshObj {
+    /// The public key as a line ready to be added to an authorized_keys file.
+    fn authorized_key(&self) -> String {
+        if self.comment.is_empty() {
+            return self.public_key.clone();
+        }
+        format!("{} {}", self.public_key, self.comment)
+    }
+
+    fn print(&self) {
+        print_result("Name", &self.ssh_name);
+        print_result("Comment", &self.comment);
+        print_result("Pubkey", &self.authorized_key());
+        if !self.passphrase.is_empty() {
+            print_result("Passphrase", self.passphrase.expose());
+        }
+        print_result("Privkey", self.private_key.expose().trim_end());
+        print_notes_and_custom_fields(&self.notes, &self.custom_fields);
+        print_tags(&self.tags);
+        print_timestamps(&self.created_at, &self.updated_at, &self.accessed_at);
+        println!();
+    }
+
+    fn print_field(&self, field: &FieldType) {
+        match field {
+            FieldType::Secname => print_result("Name", &self.ssh_name),
+            FieldType::Comment => print_result("Comment", &self.comment),
+            FieldType::Pubkey => print_result("Pubkey", &self.authorized_key()),
+            FieldType::Privkey => print_result("Privkey", self.private_key.expose().trim_end()),
+            FieldType::Passphrase => print_result("Passphrase", self.passphrase.expose()),
+            FieldType::Notes => print_result("Notes", &self.notes),
+            FieldType::Custom(key) => {
+                if let Some(f) = find_custom_field(&self.custom_fields, key) {
+                    print_result(&f.key, f.value.expose())
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn get_field(&self, field: &FieldType) -> SecretString {
+        match field {
+            FieldType::Secname => self.ssh_name.as_str().into(),
+            FieldType::Comment => self.comment.as_str().into(),
+            FieldType::Pubkey => self.authorized_key().into(),
+            FieldType::Privkey => self.private_key.clone(),
+            FieldType::Passphrase => self.passphrase.clone(),
+            FieldType::Notes => self.notes.as_str().into(),
+            FieldType::Custom(key) => find_custom_field(&self.custom_fields, key)
+                .map(|f| f.value.clone())
+                .unwrap_or_default(),
+            _ => SecretString::default(),
+        }
+    }
+
+    fn get_json_str(&self) -> String {
+        match serde_json::to_string_pretty(self) {
+            Err(_) => "".to_string(),
+            Ok(j) => j,
+        }
+    }
+
+    fn get_field_json_str(&self, field: &FieldType) -> String {
+        let json_str = match field {
+            FieldType::Secname => serde_json::json!({"Name": &self.ssh_name}),
+            FieldType::Comment => serde_json::json!({"Comment": &self.comment}),
+            FieldType::Pubkey => serde_json::json!({"Pubkey": self.authorized_key()}),
+            FieldType::Privkey => serde_json::json!({"Privkey": &self.private_key}),
+            FieldType::Passphrase => serde_json::json!({"Passphrase": &self.passphrase}),
+            FieldType::Notes => serde_json::json!({"Notes": &self.notes}),
+            FieldType::Custom(key) => match find_custom_field(&self.custom_fields, key) {
+                Some(f) => serde_json::json!({ &f.key: &f.value }),
+                None => return "".to_string(),
+            },
+            _ => return "".to_string(),
         };
+        json_str.to_string()
+    }
+
+    fn send_field_to_clipboard(&self, field: &FieldType) -> Result<(), CMError> {
+        copy_to_clipboard(self.get_field(field).expose())
+    }
+}
+
+impl NoteObj {
+    /// The body is printed as is, without a field name in front of it.
+    fn print_body(&self) {
+        let body = self.body.expose();
+        if body.ends_with('\n') {
+            print!("{}", body);
+        } else {
+            println!("{}", body);
+        }
+    }
+
+    fn print(&self) {
+        self.print_body();
+    }
+
+    fn print_field(&self, field: &FieldType) {
+        match field {
+            FieldType::Secname => print_result("Name", &self.note_name),
+            FieldType::Body => self.print_body(),
+            FieldType::Notes => print_result("Notes", &self.notes),
+            FieldType::Custom(key) => {
+                if let Some(f) = find_custom_field(&self.custom_fields, key) {
+                    print_result(&f.key, f.value.expose())
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn get_field(&self, field: &FieldType) -> SecretString {
+        match field {
+            FieldType::Secname => self.note_name.as_str().into(),
+            FieldType::Body => self.body.clone(),
+            FieldType::Notes => self.notes.as_str().into(),
+            FieldType::Custom(key) => find_custom_field(&self.custom_fields, key)
+                .map(|f| f.value.clone())
+                .unwrap_or_default(),
+            _ => SecretString::default(),
+        }
+    }
+
+    fn get_json_str(&self) -> String {
+        match serde_json::to_string_pretty(self) {
+            Err(_) => "".to_string(),
+            Ok(j) => j,
+        }
+    }
 
-        let mut clipboard = Clipboard::new()?;
+    fn get_field_json_str(&self, field: &FieldType) -> String {
+        let json_str = match field {
+            FieldType::Secname => serde_json::json!({"Name": &self.note_name}),
+            FieldType::Body => serde_json::json!({"Body": &self.body}),
+            FieldType::Notes => serde_json::json!({"Notes": &self.notes}),
+            FieldType::Custom(key) => match find_custom_field(&self.custom_fields, key) {
+                Some(f) => serde_json::json!({ &f.key: &f.value }),
+                None => return "".to_string(),
+            },
+            _ => return "".to_string(),
+        };
+        json_str.to_string()
+    }
 
-        clipboard.set_text(data)?;
-        // to stop clipboard from being dropped early which causes an error on linux
-        sleep(time::Duration::from_millis(2));
-        Ok(())
+    fn send_field_to_clipboard(&self, field: &FieldType) -> Result<(), CMError> {
+        copy_to_clipboard(self.get_field(field).expose())
     }
 }
 
 impl Secret {
-    pub fn print_field(&self, field: FieldType) {
+    pub fn print_field(&self, field: &FieldType) {
         match self {
             Self::API(apiobj) => apiobj.print_field(field),
             Self::Account(accountobj) => accountobj.print_field(field),
+            Self::Ssh(ssh) => ssh.print_field(field),
+            Self::Note(note) => note.print_field(field),
         }
     }
 
@@ -199,13 +555,17 @@ impl Secret {
         match self {
             Self::API(api) => api.print(),
             Self::Account(acc) => acc.print(),
+            Self::Ssh(ssh) => ssh.print(),
+            Self::Note(note) => note.print(),
         }
     }
 
-    pub fn get_field(&self, field: FieldType) -> String {
+    pub fn get_field(&self, field: &FieldType) -> SecretString {
         match self {
             Self::API(api) => api.get_field(field),
             Self::Account(acc) => acc.get_field(field),
+            Self::Ssh(ssh) => ssh.get_field(field),
+            Self::Note(note) => note.get_field(field),
         }
     }
 
@@ -213,13 +573,17 @@ impl Secret {
         match self {
             Self::API(api) => api.get_json_str(),
             Self::Account(acc) => acc.get_json_str(),
+            Self::Ssh(ssh) => ssh.get_json_str(),
+            Self::Note(note) => note.get_json_str(),
         }
     }
 
-    pub fn get_field_json_str(&self, field: FieldType) -> String {
+    pub fn get_field_json_str(&self, field: &FieldType) -> String {
         match self {
             Self::API(api) => api.get_field_json_str(field),
             Self::Account(acc) => acc.get_field_json_str(field),
+            Self::Ssh(ssh) => ssh.get_field_json_str(field),
+            Self::Note(note) => note.get_field_json_str(field),
         }
     }
 
@@ -227,13 +591,119 @@ impl Secret {
         match self {
             Self::Account(acc) => acc.account_name.clone(),
             Self::API(api) => api.api_name.clone(),
+            Self::Ssh(ssh) => ssh.ssh_name.clone(),
+            Self::Note(note) => note.note_name.clone(),
+        }
+    }
+
+    pub fn get_type(&self) -> SecretType {
+        match self {
+            Self::Account(_) => SecretType::Login,
+            Self::API(_) => SecretType::Api,
+            Self::Ssh(_) => SecretType::Ssh,
+            Self::Note(_) => SecretType::Note,
+        }
+    }
+
+    /// The field that is copied to the clipboard when a single secret is retrieved.
+    pub fn default_field(&self) -> FieldType {
+        match self {
+            Self::Account(_) => FieldType::Pass,
+            Self::API(_) => FieldType::Key,
+            Self::Ssh(_) => FieldType::Pubkey,
+            Self::Note(_) => FieldType::Body,
+        }
+    }
+
+    pub fn get_tags(&self) -> &[String] {
+        match self {
+            Self::Account(acc) => &acc.tags,
+            Self::API(api) => &api.tags,
+            Self::Ssh(ssh) => &ssh.tags,
+            Self::Note(note) => &note.tags,
+        }
+    }
+
+    pub fn created_at(&self) -> Option<&str> {
+        match self {
+            Self::Account(acc) => acc.created_at.as_deref(),
+            Self::API(api) => api.created_at.as_deref(),
+            Self::Ssh(ssh) => ssh.created_at.as_deref(),
+            Self::Note(note) => note.created_at.as_deref(),
+        }
+    }
+
+    pub fn updated_at(&self) -> Option<&str> {
+        match self {
+            Self::Account(acc) => acc.updated_at.as_deref(),
+            Self::API(api) => api.updated_at.as_deref(),
+            Self::Ssh(ssh) => ssh.updated_at.as_deref(),
+            Self::Note(note) => note.updated_at.as_deref(),
         }
     }
 
-    pub fn send_field_to_clipboard(&self, field: FieldType) -> Result<(), CMError> {
+    pub fn accessed_at(&self) -> Option<&str> {
+        match self {
+            Self::Account(acc) => acc.accessed_at.as_deref(),
+            Self::API(api) => api.accessed_at.as_deref(),
+            Self::Ssh(ssh) => ssh.accessed_at.as_deref(),
+            Self::Note(note) => note.accessed_at.as_deref(),
+        }
+    }
+
+    /// The fields cman search matches against as (field name, value) pairs, starting with the
+    /// name. Passwords, keys, note bodies and sensitive custom fields are never searched.
+    pub fn search_fields(&self) -> Vec<(String, SecretString)> {
+        let (mut fields, notes, tags, custom_fields) = match self {
+            Self::Account(acc) => (
+                vec![("user", &acc.user_name)],
+                &acc.notes,
+                &acc.tags,
+                &acc.custom_fields,
+            ),
+            Self::API(api) => (
+                vec![("user", &api.user_name), ("desc", &api.description)],
+                &api.notes,
+                &api.tags,
+                &api.custom_fields,
+            ),
+            Self::Ssh(ssh) => (
+                vec![("comment", &ssh.comment)],
+                &ssh.notes,
+                &ssh.tags,
+                &ssh.custom_fields,
+            ),
+            Self::Note(note) => (vec![], &note.notes, &note.tags, &note.custom_fields),
+        };
+        fields.push(("notes", notes));
+
+        let name = self.get_name();
+        let mut search_fields = vec![("secname".to_string(), name.into())];
+        search_fields.extend(
+            fields
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), value.as_str().into())),
+        );
+        search_fields.extend(
+            tags.iter()
+                .map(|tag| ("tag".to_string(), tag.as_str().into())),
+        );
+        search_fields.extend(
+            custom_fields
+                .iter()
+                .filter(|f| !f.sensitive)
+                .map(|f| (format!("custom:{}", f.key), f.value.clone())),
+        );
+        search_fields.retain(|(_, value)| !value.is_empty());
+        search_fields
+    }
+
+    pub fn send_field_to_clipboard(&self, field: &FieldType) -> Result<(), CMError> {
         match self {
             Self::Account(acc) => acc.send_field_to_clipboard(field),
             Self::API(api) => api.send_field_to_clipboard(field),
+            Self::Ssh(ssh) => ssh.send_field_to_clipboard(field),
+            Self::Note(note) => note.send_field_to_clipboard(field),
         }
     }
 }
@@ -1,4 +1,9 @@
 pub mod argparser;
+pub mod clipboard;
+pub mod config;
 pub mod errors;
 pub mod ioutils;
+pub mod otp;
 pub mod passgen;
+pub mod secret;
+pub mod sshkeys;
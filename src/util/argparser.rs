@@ -1,14 +1,49 @@
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+
+/// How the commands that take a secret name find it. A macro so that it can be appended to the
+/// help of a command with concat!.
+macro_rules! secret_lookup_note {
+    () => {
+        "Note: A secret name is looked up across all secret types unless --type is given or the name is qualified as type/name, ie api/stripe.\n\
+        If several types have the name you are asked to choose one. Without a name the secrets of the secret_type setting, or 'login', are offered."
+    };
+}
+
+const SECRET_LOOKUP_NOTE: &str = secret_lookup_note!();
 
 /// A simple tool to manage and securely store secrets like login credentials and API keys locally.
 #[derive(Parser, Debug)]
 #[command
     (version, about, long_about = None, author = "Kakeeto Pius",
-    after_long_help = "Note: cman checks the credential database file from the environment variable $CMAN_DBFILE.\n\
-If it is not set , cman defaults to $HOME/.creds.db.",
+    after_long_help = "Note: cman uses the vault given with --vault, otherwise the credential database file from the environment variable $CMAN_DBFILE.\n\
+If it is not set, the default vault is used and without one cman defaults to $HOME/.creds.db.\n\
+\nThe master password is asked for on the terminal unless it is given with --password-fd, --password-stdin or --password-env,\n\
+an agent holds the key of the vault, or $CMAN_ASKPASS names a program that prints it.",
 )]
 pub struct CmanArgs {
+    /// The name of the vault to use. Vaults are registered with cman vault add.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub vault: Option<String>,
+
+    /// The output format of commands that can print json. Overrides the output setting of the
+    /// config file. --json always selects json.
+    #[arg(value_enum, long, global = true)]
+    pub format: Option<OutputFormat>,
+
+    /// Read the master password from the first line of this open file descriptor.
+    #[arg(long, global = true, value_name = "FD", conflicts_with_all = ["password_stdin", "password_env"])]
+    pub password_fd: Option<u32>,
+
+    /// Read the master password from the first line of stdin.
+    #[arg(long, global = true, conflicts_with = "password_env")]
+    pub password_stdin: bool,
+
+    /// Read the master password from the named environment variable.
+    #[arg(long, global = true, value_name = "VAR")]
+    pub password_env: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -23,32 +58,165 @@ pub enum Commands {
         after_long_help = "Rules for batch file:\n1. Each line has comma separated details of a single secret with the type as the first field\n\
         2. For type 'login' the format is login,secretname,username,password\n3. For type 'api' the format is api,secretname,username,description,key\n\
         4. If it is required that a given login credential's password is automatically generated, use ? as a placeholder ie login,secretname,username,?\n\
-        \n Note: If the --type argument is not given 'login' is assumed."
+        5. Optional fields can follow the required ones: notes=<text> sets the notes, key=value adds a custom field and !key=value adds a sensitive custom field\n\
+        ie login,secretname,username,?,notes=personal account,!recovery=1234-5678\n\
+        \nThe body of a note (--type note) is read from stdin if it is not a terminal, otherwise $EDITOR is opened.\n\
+        \n Note: If the --type argument is not given the secret_type setting, or 'login', is assumed."
     )]
     Add(AddArgs),
 
     /// Alter details of a particular secret.
-    #[command(after_long_help = "Note: If the --type argument is not given 'login' is assumed.")]
+    #[command(after_long_help = SECRET_LOOKUP_NOTE)]
     Change(ChangeArgs),
 
     /// Retrieve details about one or more secrets.
-    #[command(after_long_help = "Note: If the --type argument is not given 'login' is assumed.")]
+    #[command(after_long_help = SECRET_LOOKUP_NOTE)]
     Get(GetArgs),
 
-    /// Delete one or more secrets permanently from storage. Use with care.
-    #[command(after_long_help = "Note: If the --type argument is not given 'login' is assumed.")]
+    /// Move one or more secrets to the trash, or delete them permanently with --permanent.
+    #[command(
+        after_long_help = concat!(
+            "Trashed secrets can be brought back with cman restore <name> until the trash is purged.\n\n",
+            secret_lookup_note!()
+        )
+    )]
     Delete(DeleteArgs),
 
+    /// Bring a secret back from the trash.
+    #[command(
+        after_long_help = "Note: If the --type argument is not given the secret_type setting, or 'login', is assumed."
+    )]
+    Restore(RestoreArgs),
+
+    /// List or purge the secrets in the trash.
+    Trash(TrashArgs),
+
+    /// Search all secrets by name, username, description, notes, tags and custom fields.
+    #[command(
+        after_long_help = "Results are ranked by how well they fuzzy match the query, names weighing more than other fields.\n\
+        With --fts the full text index stored in the encrypted database is used instead, which matches the start of every word in the query.\n\
+        Passwords, keys, note bodies and sensitive custom fields are never searched.\n\
+        \nNote: All secret types are searched unless --type is given."
+    )]
+    Search(SearchArgs),
+
     /// List all stored secrets of a particular type.
-    #[command(after_long_help = "Note: If the --type argument is not given 'login' is assumed.")]
+    #[command(
+        after_long_help = "Note: If the --type argument is not given the secret_type setting, or 'login', is assumed."
+    )]
     Ls(LsArgs),
 
+    /// Show the previous passwords, api keys, private keys or note bodies of a secret.
+    #[command(
+        after_long_help = concat!(
+            "Entries are numbered from the most recently replaced value. Use cman change <name> --restore <n> to bring one back.\n\n",
+            secret_lookup_note!()
+        )
+    )]
+    History(HistoryArgs),
+
+    /// Store a file such as a certificate or a kubeconfig with a secret.
+    #[command(
+        after_long_help = concat!(
+            "Use cman attachments <name> to list the stored files and cman get <name> --attachment <file> --out <path> to extract one.\n\n",
+            secret_lookup_note!()
+        )
+    )]
+    Attach(AttachArgs),
+
+    /// List or remove the files attached to a secret.
+    #[command(after_long_help = SECRET_LOOKUP_NOTE)]
+    Attachments(AttachmentsArgs),
+
+    /// Print the current one time password of a login credential and copy it to the clipboard.
+    #[command(
+        after_long_help = "The seed is set with cman change <name> --field otp using an otpauth:// URI or a base32 secret."
+    )]
+    Otp(OtpArgs),
+
+    /// Generate passwords or passphrases without storing them. The vault is not opened.
+    #[command(
+        after_long_help = "The generator settings of the config file apply as they do for cman add. The entropy is an estimate\n\
+        of how many guesses it takes to find a generated value, as a power of two, when the policy it was made with is known."
+    )]
+    Generate(GenerateArgs),
+
+    /// Show the audit log of operations on the vault.
+    #[command(
+        after_long_help = "Dates are given as YYYY-MM-DD or YYYY-MM-DD HH:MM:SS in UTC. A --until date without a time includes that whole day."
+    )]
+    Log(LogArgs),
+
     /// Pull the credential database from a remote url.
     #[command(
-        after_long_help = "The url can be provided via the environment variable CMAN_DBURL or via the --url flag."
+        after_long_help = "The url can be provided via the --url flag, the url registered with the vault or the environment variable CMAN_DBURL."
     )]
     Pull(PullArgs),
 
+    /// Manage the credential database itself.
+    Db(DbArgs),
+
+    /// Start an agent that keeps unlocked vaults unlocked until it has been idle for a while.
+    #[command(
+        after_long_help = "Like ssh-agent, the agent keeps the key of every vault unlocked while it runs in memory and hands it\n\
+        to later cman commands over a socket only you can reach, so that the password is asked for once. The agent only\n\
+        holds the encryption key derived from the master password, never the password itself.\n\
+        The socket is $CMAN_AGENT_SOCK, or cman/agent.sock in $XDG_RUNTIME_DIR or $HOME/.cache.\n\
+        Use cman lock to forget the keys and stop the agent."
+    )]
+    Agent(AgentArgs),
+
+    /// Make the agent forget every key and stop it.
+    Lock,
+
+    /// Create keyfiles that lock a database together with, or instead of, the master password.
+    #[command(
+        after_long_help = "A database locked with a keyfile needs it every time it is opened. cman finds it through $CMAN_KEYFILE,\n\
+        then the keyfile of the selected vault and finally the keyfile setting. Keep a backup of the keyfile: without it the database cannot be opened."
+    )]
+    Keyfile(KeyfileArgs),
+
+    /// Manage the named vaults cman can switch between with --vault.
+    #[command(
+        after_long_help = "Vaults are kept in $XDG_CONFIG_HOME/cman/config.toml, or $HOME/.config/cman/config.toml if that variable is not set.\n\
+        The $CMAN_CONFIG environment variable can point cman to a different config file."
+    )]
+    Vault(VaultArgs),
+
+    /// Read or change the settings in the config file.
+    #[command(after_long_help = "Settings:\n\
+        default_vault              The vault used when --vault is not given\n\
+        db_path                    The database used when there is no default vault instead of $HOME/.creds.db\n\
+        keyfile                    The keyfile of the database used when no vault is selected\n\
+        secret_type                The secret type assumed when --type is not given\n\
+        output                     text or json\n\
+        remote_url                 The url cman pull uses when neither --url, the vault nor $CMAN_DBURL give one\n\
+        clip_timeout               Seconds after which a copied secret is cleared from the clipboard, 30 by default, 0 to keep it\n\
+        generator.length           The length of generated passwords\n\
+        generator.uppercase        Whether generated passwords contain uppercase letters\n\
+        generator.lowercase        Whether generated passwords contain lowercase letters\n\
+        generator.digits           Whether generated passwords contain digits\n\
+        generator.symbols          Whether generated passwords contain symbols\n\
+        generator.min_uppercase    The least number of uppercase letters in generated passwords\n\
+        generator.min_lowercase    The least number of lowercase letters in generated passwords\n\
+        generator.min_digits       The least number of digits in generated passwords\n\
+        generator.min_symbols      The least number of symbols in generated passwords\n\
+        generator.exclude          Characters generated passwords never contain\n\
+        generator.avoid_ambiguous  Whether characters that look alike such as 0 and O are left out\n\
+        generator.alphabet         The characters passwords are drawn from instead of the classes\n\
+        generator.passphrase       Whether passphrases of words are generated instead of passwords\n\
+        generator.words            The number of words in a passphrase, 6 by default\n\
+        generator.separator        What goes between the words of a passphrase, - by default\n\
+        generator.capitalize       Whether every word of a passphrase starts with a capital letter\n\
+        generator.add_digit        Whether a digit is added to passphrases\n\
+        generator.add_symbol       Whether a symbol is added to passphrases")]
+    Config(ConfigArgs),
+
+    /// Keep a secret read from stdin on the clipboard and clear it after the timeout. cman starts
+    /// this itself in the background when it copies a secret.
+    #[command(hide = true)]
+    Clip(ClipArgs),
+
     /// Generate shell completions
     #[command(hide = true)]
     Completions {
@@ -63,9 +231,132 @@ pub struct InitArgs {
     #[arg(
         short,
         long,
-        long_help = "The path to initialise the database. If not given $CMAN_DBFILE is used if that variable is not set, $HOME/.creds.db is used."
+        long_help = "The path to initialise the database. If not given the path of the vault selected with --vault is used,\n\
+        then $CMAN_DBFILE, then the default vault and finally $HOME/.creds.db.\n\
+        If --vault names a vault that is not registered yet, it is registered with this path."
     )]
     pub path: Option<String>,
+
+    /// Lock the database with this keyfile as well as the master password. Any file works, or
+    /// one made with cman keyfile new.
+    #[arg(short, long, value_name = "FILE")]
+    pub keyfile: Option<String>,
+
+    /// Lock the database with the keyfile alone.
+    #[arg(long, requires = "keyfile")]
+    pub no_password: bool,
+
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+}
+
+/// How the encryption key is derived from the master password. Anything not given keeps its
+/// current value, or the SQLCipher default for a new database.
+#[derive(Args, Debug)]
+pub struct CipherArgs {
+    /// The number of PBKDF2 iterations. More make unlocking, and guessing the password, slower.
+    /// The SQLCipher default is 256000.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub kdf_iter: Option<u32>,
+
+    /// Pick the number of iterations that takes about this many milliseconds to unlock on this
+    /// machine instead of giving --kdf-iter.
+    #[arg(long, value_name = "MS", conflicts_with = "kdf_iter", value_parser = clap::value_parser!(u64).range(1..))]
+    pub unlock_time: Option<u64>,
+
+    /// The hash used by PBKDF2. The default is sha512.
+    #[arg(value_enum, long)]
+    pub kdf_algorithm: Option<CipherAlgorithm>,
+
+    /// The HMAC that protects every page from tampering. The default is sha512.
+    #[arg(value_enum, long)]
+    pub hmac_algorithm: Option<CipherAlgorithm>,
+
+    /// The database page size in bytes, a power of two from 512 to 65536. The default is 4096.
+    #[arg(long, value_name = "BYTES", value_parser = parse_page_size)]
+    pub page_size: Option<u32>,
+}
+
+/// Which characters generated passwords are made of. Anything given overrides the generator
+/// settings for this command.
+#[derive(Args, Debug)]
+#[group(id = "policy_args", multiple = true)]
+pub struct PolicyArgs {
+    /// Leave uppercase letters out of the password.
+    #[arg(long, conflicts_with = "alphabet")]
+    pub no_uppercase: bool,
+
+    /// Leave lowercase letters out of the password.
+    #[arg(long, conflicts_with = "alphabet")]
+    pub no_lowercase: bool,
+
+    /// Leave digits out of the password.
+    #[arg(long, conflicts_with = "alphabet")]
+    pub no_digits: bool,
+
+    /// Leave symbols out of the password.
+    #[arg(long, conflicts_with = "alphabet")]
+    pub no_symbols: bool,
+
+    /// The least number of uppercase letters in the password. Every class that is used appears
+    /// at least once by default.
+    #[arg(long, value_name = "N", conflicts_with = "no_uppercase")]
+    pub min_uppercase: Option<usize>,
+
+    /// The least number of lowercase letters in the password.
+    #[arg(long, value_name = "N", conflicts_with = "no_lowercase")]
+    pub min_lowercase: Option<usize>,
+
+    /// The least number of digits in the password.
+    #[arg(long, value_name = "N", conflicts_with = "no_digits")]
+    pub min_digits: Option<usize>,
+
+    /// The least number of symbols in the password.
+    #[arg(long, value_name = "N", conflicts_with = "no_symbols")]
+    pub min_symbols: Option<usize>,
+
+    /// Characters that must not appear in the password.
+    #[arg(long, value_name = "CHARS")]
+    pub exclude: Option<String>,
+
+    /// Leave out characters that are easily mistaken for one another such as 0, O, 1, l and I.
+    #[arg(long)]
+    pub avoid_ambiguous: bool,
+
+    /// Draw the password from these characters instead of the classes. Anything that is not a
+    /// letter or digit counts as a symbol for the minimums.
+    #[arg(long, value_name = "CHARS")]
+    pub alphabet: Option<String>,
+}
+
+/// How generated passphrases look. Giving any of these generates a passphrase of words instead
+/// of a password.
+#[derive(Args, Debug)]
+#[group(id = "passphrase_args", multiple = true, conflicts_with_all = ["passlen", "policy_args"])]
+pub struct PassphraseArgs {
+    /// Generate a passphrase of words, which is easier to type by hand than a password.
+    #[arg(long)]
+    pub passphrase: bool,
+
+    /// The number of words in the passphrase. The default is the generator.words setting or 6.
+    #[arg(long, value_name = "N")]
+    pub words: Option<usize>,
+
+    /// What goes between the words of the passphrase. The default is -.
+    #[arg(long, value_name = "SEP")]
+    pub separator: Option<String>,
+
+    /// Start every word of the passphrase with a capital letter.
+    #[arg(long)]
+    pub capitalize: bool,
+
+    /// Add a random digit to the end of one of the words.
+    #[arg(long)]
+    pub add_digit: bool,
+
+    /// Add a random symbol to the end of one of the words.
+    #[arg(long)]
+    pub add_symbol: bool,
 }
 
 #[derive(Args, Debug)]
@@ -78,14 +369,43 @@ pub struct AddArgs {
     #[arg(value_enum, short = 't', long = "type")]
     pub secret_type: Option<SecretType>,
 
-    /// The length of the password to generate. The default is 16 characters.
+    /// The length of the password to generate. The default is the generator.length setting or
+    /// 16 characters.
     #[arg(short = 'l', long = "len")]
     pub passlen: Option<usize>,
 
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
     /// Do not automatically generate the password, the user is instead prompted for one.
     #[arg(long = "no-auto")]
     pub no_auto: bool,
 
+    /// Tag the secret. Can be given more than once.
+    #[arg(long = "tag", value_name = "TAG", value_parser = parse_tag)]
+    pub tags: Vec<String>,
+
+    /// Generate a new ssh key pair with the given algorithm. This is the default for ssh keys.
+    #[arg(
+        value_enum,
+        short,
+        long,
+        value_name = "ALGORITHM",
+        conflicts_with = "import"
+    )]
+    pub generate: Option<KeyAlgorithm>,
+
+    /// Import an existing ssh private key in OpenSSH format from a file.
+    #[arg(short, long, value_name = "FILE")]
+    pub import: Option<String>,
+
+    /// The comment of an ssh key. Defaults to the comment of an imported key or the secret name.
+    #[arg(short, long)]
+    pub comment: Option<String>,
+
     /// The SECRET_NAME is treated as file containing credentials one per line (Use cman add --help for more details).
     #[arg(
         short,
@@ -105,17 +425,171 @@ pub struct ChangeArgs {
     #[arg(value_enum, short = 't', long = "type")]
     pub secret_type: Option<SecretType>,
 
-    /// The field of the secret to change.
-    #[arg(value_enum, short, long = "field")]
+    /// The field of the secret to change. One of user, secname, pass, otp, desc, key, notes,
+    /// privkey, passphrase, comment, body or custom:<key> for a custom field. Leaving a custom field or
+    /// the otp seed empty removes it. Changing the privkey of an ssh key generates a new key pair.
+    #[arg(short, long = "field", value_parser = parse_field)]
     pub field: Option<FieldType>,
 
-    /// The length of the password to generate. The default is 16 characters.
+    /// Mark the custom field being changed as sensitive so that its value is masked unless
+    /// requested explicitly. A field that is already sensitive stays sensitive.
+    #[arg(long, requires = "field")]
+    pub sensitive: bool,
+
+    /// The length of the password to generate. The default is the generator.length setting or
+    /// 16 characters.
     #[arg(short = 'l', long = "len")]
     pub passlen: Option<usize>,
 
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
     /// Do not automatically generate a password, the user is instead prompted for one.
     #[arg(long = "no-auto")]
     pub no_auto: bool,
+
+    /// With master, lock the database with this keyfile as well as the new master password.
+    /// Without it a keyfile the database had is no longer needed.
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["field", "restore"])]
+    pub keyfile: Option<String>,
+
+    /// With master, lock the database with the keyfile alone.
+    #[arg(long, requires = "keyfile")]
+    pub no_password: bool,
+
+    /// Restore the password or api key numbered N in the output of cman history. The current
+    /// value is kept in the history.
+    #[arg(short, long, value_name = "N", conflicts_with_all = ["field", "no_auto", "passlen"])]
+    pub restore: Option<usize>,
+
+    /// Add a tag to the secret. Can be given more than once.
+    #[arg(long = "add-tag", value_name = "TAG", value_parser = parse_tag, conflicts_with_all = ["field", "restore"])]
+    pub add_tags: Vec<String>,
+
+    /// Remove a tag from the secret. Can be given more than once.
+    #[arg(long = "remove-tag", value_name = "TAG", value_parser = parse_tag, conflicts_with_all = ["field", "restore"])]
+    pub remove_tags: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// The name of the secret to show the history for.
+    pub secret: Option<String>,
+
+    /// The type of Secret.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Print the results returned in json form.
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AttachArgs {
+    /// The name of the secret to attach the file to.
+    pub secret: String,
+
+    /// The file to attach.
+    pub file: String,
+
+    /// The type of Secret.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Store the file under this name instead of its file name.
+    #[arg(short, long)]
+    pub name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AttachmentsArgs {
+    /// The name of the secret to list the attachments of.
+    pub secret: Option<String>,
+
+    /// The type of Secret.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Remove the attachment with this name instead of listing them.
+    #[arg(short, long, value_name = "FILE")]
+    pub remove: Option<String>,
+
+    /// Print the results returned in json form.
+    #[arg(short, long, conflicts_with = "remove")]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LogArgs {
+    /// Only show entries for the secret with this name.
+    pub secret: Option<String>,
+
+    /// Only show entries for secrets of this type.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Only show entries for this operation.
+    #[arg(value_enum, short, long)]
+    pub operation: Option<AuditOperation>,
+
+    /// Only show entries logged on or after this date.
+    #[arg(long, value_name = "DATE", value_parser = parse_since)]
+    pub since: Option<String>,
+
+    /// Only show entries logged on or before this date.
+    #[arg(long, value_name = "DATE", value_parser = parse_until)]
+    pub until: Option<String>,
+
+    /// Only show the most recent N entries.
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Print the results returned in json form.
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct OtpArgs {
+    /// The name of the login credential to generate a code for.
+    pub secret: Option<String>,
+
+    /// Only print the code.
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// The length of the passwords. The default is the generator.length setting or 16 characters.
+    #[arg(short = 'l', long = "len")]
+    pub passlen: Option<usize>,
+
+    /// How many passwords to generate.
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    pub count: u32,
+
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// Copy the password to the clipboard as well.
+    #[arg(short, long, conflicts_with = "count")]
+    pub clip: bool,
+
+    /// Only print the passwords, without the entropy.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Print the results in json form.
+    #[arg(short, long)]
+    pub json: bool,
 }
 
 #[derive(Args, Debug)]
@@ -127,10 +601,24 @@ pub struct GetArgs {
     #[arg(value_enum, short = 't', long = "type")]
     pub secret_type: Option<SecretType>,
 
-    /// Get a particular field of the secret(s)
-    #[arg(value_enum, short, long = "field")]
+    /// Get a particular field of the secret(s). One of user, secname, pass, otp, desc, key, notes,
+    /// privkey, pubkey, passphrase, comment, body or custom:<key> for a custom field.
+    #[arg(short, long = "field", value_parser = parse_field)]
     pub field: Option<FieldType>,
 
+    /// Only offer secrets with this tag in the interactive menu. Can be given more than once.
+    #[arg(long = "tag", value_name = "TAG", value_parser = parse_tag, conflicts_with = "secret")]
+    pub tags: Vec<String>,
+
+    /// Extract the attachment with this name instead of printing the secret.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["field", "multiple", "json"])]
+    pub attachment: Option<String>,
+
+    /// Where to write the extracted attachment. Defaults to the attachment name in the current
+    /// directory. Existing files are never overwritten.
+    #[arg(short, long, value_name = "PATH", requires = "attachment")]
+    pub out: Option<String>,
+
     /// Accept mutliple inputs from the interactive menu provided only if no secret name is given
     /// as part of the command line arguments.
     #[arg(short, long)]
@@ -143,6 +631,15 @@ pub struct GetArgs {
     /// Print the results returned in json form.
     #[arg(short, long)]
     pub json: bool,
+
+    /// Clear the copied secret from the clipboard after this many seconds, 0 to keep it. Overrides
+    /// the clip_timeout setting, which defaults to 30 seconds.
+    #[arg(long, value_name = "SECONDS")]
+    pub clip_timeout: Option<u64>,
+
+    /// Do not copy the secret to the clipboard.
+    #[arg(long, conflicts_with = "clip_timeout")]
+    pub no_clip: bool,
 }
 
 #[derive(Args, Debug)]
@@ -158,6 +655,86 @@ pub struct DeleteArgs {
     /// as part of the command line arguments..
     #[arg(short, long)]
     pub multiple: bool,
+
+    /// Delete the secret(s) for good instead of moving them to the trash.
+    #[arg(long)]
+    pub permanent: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// The name of the secret to restore. If not given the trashed secrets are offered in a menu.
+    pub secret: Option<String>,
+
+    /// The type of Secret.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+}
+
+#[derive(Args, Debug)]
+pub struct TrashArgs {
+    #[command(subcommand)]
+    pub command: TrashCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommands {
+    /// List the secrets in the trash.
+    Ls(TrashLsArgs),
+
+    /// Permanently delete secrets from the trash.
+    #[command(
+        after_long_help = "Ages are a number followed by s, m, h, d or w ie 30d for thirty days."
+    )]
+    Purge(PurgeArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TrashLsArgs {
+    /// Only list trashed secrets of this type. All types are listed if not given.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Print the results returned in json form.
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PurgeArgs {
+    /// Only purge secrets that were trashed at least this long ago ie 30d.
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    pub older_than: Option<u64>,
+
+    /// Only purge trashed secrets of this type. All types are purged if not given.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Do not ask for confirmation.
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// The text to search for.
+    pub query: String,
+
+    /// Only search secrets of this type.
+    #[arg(value_enum, short = 't', long = "type")]
+    pub secret_type: Option<SecretType>,
+
+    /// Use the full text search index instead of fuzzy matching.
+    #[arg(long)]
+    pub fts: bool,
+
+    /// Show at most this many results.
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Print the results returned in json form.
+    #[arg(short, long)]
+    pub json: bool,
 }
 
 #[derive(Args, Debug)]
@@ -166,6 +743,14 @@ pub struct LsArgs {
     #[arg(value_enum, short = 't', long = "type")]
     pub secret_type: Option<SecretType>,
 
+    /// Sort the secrets by name or by one of their timestamps. Timestamps are sorted newest first.
+    #[arg(value_enum, short, long)]
+    pub sort: Option<SortKey>,
+
+    /// Only list secrets with this tag. If given more than once a secret must have all of them.
+    #[arg(long = "tag", value_name = "TAG", value_parser = parse_tag)]
+    pub tags: Vec<String>,
+
     /// Print the results returned in json form.
     #[arg(short, long)]
     pub json: bool,
@@ -177,22 +762,301 @@ pub struct PullArgs {
     #[arg(short = 'u', long = "url")]
     pub url: Option<String>,
 
-    /// The file to write the remote database to. Defaults to CMAN_DBFILE environment variable or
-    /// the $HOME/.creds.db file if the environment variable is missing.
+    /// The file to write the remote database to. Defaults to the database cman would otherwise
+    /// open: the --vault vault, $CMAN_DBFILE, the default vault or $HOME/.creds.db.
     #[arg(short, long)]
     pub out: Option<String>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+#[derive(Args, Debug)]
+pub struct AgentArgs {
+    /// Stop the agent after this many seconds without a request. The default is 900 seconds.
+    #[arg(short, long, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+
+    /// Run the agent in the foreground instead of in the background.
+    #[arg(long)]
+    pub foreground: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipArgs {
+    /// Seconds after which the clipboard is cleared.
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyfileArgs {
+    #[command(subcommand)]
+    pub command: KeyfileCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyfileCommands {
+    /// Write a new keyfile of random bytes that only you can read.
+    New(KeyfileNewArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct KeyfileNewArgs {
+    /// Where to write the keyfile. An existing file is never overwritten.
+    pub path: String,
+}
+
+#[derive(Args, Debug)]
+pub struct DbArgs {
+    #[command(subcommand)]
+    pub command: DbCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommands {
+    /// Upgrade the database schema to the version used by this release of cman.
+    #[command(
+        after_long_help = "Note: pending migrations are also applied automatically whenever the database is opened by any other command."
+    )]
+    Migrate(MigrateArgs),
+
+    /// Re-encrypt the database with new key derivation or cipher parameters, ie to raise the
+    /// number of iterations.
+    #[command(
+        after_long_help = "The parameters are kept in a plaintext file next to the database, its path with .cipher appended.\n\
+        Copy that file along with the database, without it cman assumes the SQLCipher defaults."
+    )]
+    Rekdf(RekdfArgs),
+
+    /// Suggest the number of key derivation iterations for a target unlock time on this machine.
+    Benchmark(BenchmarkArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct RekdfArgs {
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchmarkArgs {
+    /// The time unlocking should take in milliseconds.
+    #[arg(short, long, value_name = "MS", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub unlock_time: u64,
+
+    /// The hash used by PBKDF2.
+    #[arg(value_enum, long, default_value = "sha512")]
+    pub kdf_algorithm: CipherAlgorithm,
+}
+
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    /// Only report the pending migrations without applying them.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VaultArgs {
+    #[command(subcommand)]
+    pub command: VaultCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VaultCommands {
+    /// List the registered vaults. The default vault is marked with *.
+    Ls(VaultLsArgs),
+
+    /// Register a credential database under a name.
+    #[command(
+        after_long_help = "Registering a vault does not create the database. Use cman --vault <name> init for a new one."
+    )]
+    Add(VaultAddArgs),
+
+    /// Forget a vault. The database file itself is left in place.
+    Remove(VaultRemoveArgs),
+
+    /// Set the vault used when --vault is not given.
+    Default(VaultDefaultArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct VaultLsArgs {
+    /// Print the results returned in json form.
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VaultAddArgs {
+    /// The name of the vault.
+    pub name: String,
+
+    /// The path of the vault's database file.
+    pub path: String,
+
+    /// The remote url cman pull fetches this vault from.
+    #[arg(short = 'u', long = "url")]
+    pub url: Option<String>,
+
+    /// The keyfile that unlocks the vault together with, or instead of, the master password.
+    #[arg(short, long, value_name = "FILE")]
+    pub keyfile: Option<String>,
+
+    /// Make this the default vault.
+    #[arg(short, long)]
+    pub default: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VaultRemoveArgs {
+    /// The name of the vault.
+    pub name: String,
+}
+
+#[derive(Args, Debug)]
+pub struct VaultDefaultArgs {
+    /// The name of the vault. If not given the default is unset.
+    pub name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the value of a setting.
+    Get(ConfigGetArgs),
+
+    /// Change a setting.
+    Set(ConfigSetArgs),
+
+    /// Remove a setting so that the built in default is used again.
+    Unset(ConfigGetArgs),
+
+    /// List all settings that are set.
+    List,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigGetArgs {
+    /// The name of the setting. See cman config --help for the list.
+    pub key: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigSetArgs {
+    /// The name of the setting. See cman config --help for the list.
+    pub key: String,
+
+    /// The new value.
+    pub value: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CipherAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human readable text.
+    Text,
+
+    /// Pretty printed json.
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SecretType {
     /// The secret is a login credential.
     Login,
 
     /// The secret is an API key.
     Api,
+
+    /// The secret is an ssh key pair.
+    Ssh,
+
+    /// The secret is a multi-line note.
+    Note,
 }
 
+/// The operations recorded in the audit log.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum AuditOperation {
+    /// A secret was added.
+    Add,
+
+    /// A secret, one of its fields, its history or an attachment was read.
+    Get,
+
+    /// A field, tag, custom field or attachment of a secret was changed.
+    Change,
+
+    /// A secret was moved to the trash.
+    Trash,
+
+    /// A secret was restored from the trash.
+    Restore,
+
+    /// A secret was deleted permanently.
+    Delete,
+
+    /// The database was pulled from a remote url.
+    Pull,
+
+    /// The master password was changed.
+    Master,
+
+    /// The database was re-encrypted with new key derivation or cipher parameters.
+    Rekdf,
+}
+
+impl AuditOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Get => "get",
+            Self::Change => "change",
+            Self::Trash => "trash",
+            Self::Restore => "restore",
+            Self::Delete => "delete",
+            Self::Pull => "pull",
+            Self::Master => "master",
+            Self::Rekdf => "rekdf",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum KeyAlgorithm {
+    /// An Ed25519 key pair.
+    Ed25519,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum SortKey {
+    /// The name of the secret.
+    Name,
+
+    /// When the secret was added.
+    Created,
+
+    /// When the secret was last changed.
+    Updated,
+
+    /// When the secret was last retrieved.
+    Accessed,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum FieldType {
     /// The username for the secret.
     User,
@@ -203,9 +1067,181 @@ pub enum FieldType {
     /// The Password (LOGIN ONLY)
     Pass,
 
+    /// The otpauth:// URI of a two factor authentication seed. (LOGIN ONLY)
+    Otp,
+
     /// The description of an api key (API ONLY)
     Desc,
 
     /// The API Key. (API ONLY)
     Key,
+
+    /// Free-form notes about the secret.
+    Notes,
+
+    /// The private key of an ssh key pair. (SSH ONLY)
+    Privkey,
+
+    /// The public key of an ssh key pair as an authorized_keys line. (SSH ONLY)
+    Pubkey,
+
+    /// The passphrase protecting an ssh private key. (SSH ONLY)
+    Passphrase,
+
+    /// The comment of an ssh key pair. (SSH ONLY)
+    Comment,
+
+    /// The text of a note. (NOTE ONLY)
+    Body,
+
+    /// A user defined field, given on the command line as custom:<key>.
+    Custom(String),
+}
+
+impl FieldType {
+    /// The name the field is given on the command line.
+    pub fn name(&self) -> String {
+        let name = match self {
+            Self::User => "user",
+            Self::Secname => "secname",
+            Self::Pass => "pass",
+            Self::Otp => "otp",
+            Self::Desc => "desc",
+            Self::Key => "key",
+            Self::Notes => "notes",
+            Self::Privkey => "privkey",
+            Self::Pubkey => "pubkey",
+            Self::Passphrase => "passphrase",
+            Self::Comment => "comment",
+            Self::Body => "body",
+            Self::Custom(key) => return format!("custom:{}", key),
+        };
+        name.to_string()
+    }
+}
+
+/// Tags are stored comma separated when read back from the database so they cannot contain commas.
+fn parse_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("tag cannot be empty".to_string());
+    }
+    if tag.contains(',') {
+        return Err("tag cannot contain a comma".to_string());
+    }
+    Ok(tag.to_string())
+}
+
+/// SQLCipher only accepts page sizes that are powers of two within SQLite's limits.
+fn parse_page_size(size: &str) -> Result<u32, String> {
+    let size: u32 = size
+        .parse()
+        .map_err(|_| format!("{} is not a number", size))?;
+    if !(512..=65536).contains(&size) || !size.is_power_of_two() {
+        return Err("page size must be a power of two from 512 to 65536".to_string());
+    }
+    Ok(size)
+}
+
+fn parse_field(field: &str) -> Result<FieldType, String> {
+    if let Some(key) = field.strip_prefix("custom:") {
+        return parse_custom_key(key).map(FieldType::Custom);
+    }
+
+    match field.to_lowercase().as_str() {
+        "user" => Ok(FieldType::User),
+        "secname" => Ok(FieldType::Secname),
+        "pass" => Ok(FieldType::Pass),
+        "otp" => Ok(FieldType::Otp),
+        "desc" => Ok(FieldType::Desc),
+        "key" => Ok(FieldType::Key),
+        "notes" => Ok(FieldType::Notes),
+        "privkey" => Ok(FieldType::Privkey),
+        "pubkey" => Ok(FieldType::Pubkey),
+        "passphrase" => Ok(FieldType::Passphrase),
+        "comment" => Ok(FieldType::Comment),
+        "body" => Ok(FieldType::Body),
+        _ => Err(format!(
+            "invalid field '{}'. Possible values: user, secname, pass, otp, desc, key, notes, privkey, pubkey, passphrase, comment, body, custom:<key>",
+            field
+        )),
+    }
+}
+
+/// Checks a YYYY-MM-DD date with an optional HH:MM or HH:MM:SS time and returns it in the format
+/// the timestamps are stored in. `end_of_day` fills in a missing time with the last second of
+/// the day instead of the first one.
+fn parse_date(date: &str, end_of_day: bool) -> Result<String, String> {
+    let date = date.trim().replace('T', " ");
+    let invalid = || {
+        format!(
+            "invalid date '{}'. Use YYYY-MM-DD or YYYY-MM-DD HH:MM:SS",
+            date
+        )
+    };
+    let pattern = match date.len() {
+        10 => "dddd-dd-dd",
+        16 => "dddd-dd-dd dd:dd",
+        19 => "dddd-dd-dd dd:dd:dd",
+        _ => return Err(invalid()),
+    };
+    let matches = date.chars().zip(pattern.chars()).all(|(c, p)| match p {
+        'd' => c.is_ascii_digit(),
+        _ => c == p,
+    });
+    if !matches {
+        return Err(invalid());
+    }
+
+    let normalised = match date.len() {
+        10 if end_of_day => format!("{} 23:59:59", date),
+        10 => format!("{} 00:00:00", date),
+        16 if end_of_day => format!("{}:59", date),
+        16 => format!("{}:00", date),
+        _ => date,
+    };
+    Ok(normalised)
+}
+
+fn parse_since(date: &str) -> Result<String, String> {
+    parse_date(date, false)
+}
+
+fn parse_until(date: &str) -> Result<String, String> {
+    parse_date(date, true)
+}
+
+/// Parses an age like 30d into seconds.
+fn parse_age(age: &str) -> Result<u64, String> {
+    let age = age.trim();
+    let invalid = || {
+        format!(
+            "invalid age '{}'. Use a number followed by s, m, h, d or w",
+            age
+        )
+    };
+    let unit = age.chars().last().ok_or_else(invalid)?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = age[..age.len() - 1].parse().map_err(|_| invalid())?;
+    count.checked_mul(seconds).ok_or_else(invalid)
+}
+
+/// Custom field keys share the batch file format with other fields so they cannot contain commas
+/// or equal signs.
+pub fn parse_custom_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("custom field key cannot be empty".to_string());
+    }
+    if key.contains(',') || key.contains('=') {
+        return Err("custom field key cannot contain a comma or an equal sign".to_string());
+    }
+    Ok(key.to_string())
 }
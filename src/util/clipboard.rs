@@ -0,0 +1,138 @@
+use crate::util::config::settings;
+use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
+
+use arboard::Clipboard;
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Seconds a copied secret stays on the clipboard when the clip_timeout setting is not set.
+pub const DEFAULT_CLIP_TIMEOUT: u64 = 30;
+/// What the clipboard process prints once the secret is on the clipboard.
+const READY: &str = "ready";
+
+/// Copies data to the clipboard. Unless the clip_timeout setting is 0, a background cman process
+/// takes the data over and clears the clipboard after that many seconds, if nothing else has been
+/// copied in the meantime.
+pub fn copy_to_clipboard(data: &str) -> Result<(), CMError> {
+    let timeout = settings().clip_timeout.unwrap_or(DEFAULT_CLIP_TIMEOUT);
+    if timeout == 0 {
+        let mut clipboard = Clipboard::new()?;
+        clipboard.set_text(data)?;
+        // to stop clipboard from being dropped early which causes an error on linux
+        sleep(Duration::from_millis(2));
+        return Ok(());
+    }
+
+    // the process is the same binary started again. The data is passed on stdin so that it never
+    // shows up in the process list.
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(["clip", "--timeout", &timeout.to_string()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data.as_bytes())?;
+    }
+    let mut reply = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut reply)?;
+    }
+    let reply = reply.trim_end();
+    if reply != READY {
+        let _ = child.wait();
+        let reason = if reply.is_empty() {
+            "the clipboard process exited"
+        } else {
+            reply
+        };
+        return Err(
+            CustomError::new(&format!("Could not copy to the clipboard: {}", reason)).into(),
+        );
+    }
+
+    eprintln!("Clearing the clipboard in {} seconds", timeout);
+    Ok(())
+}
+
+/// Runs in the process started by copy_to_clipboard. The data read from stdin is put on the
+/// clipboard and, after `timeout` seconds, cleared unless it has been replaced. On Linux the
+/// clipboard only holds data as long as a process serves it, so this process owns the selection
+/// until then and stops early when another program takes it over.
+pub fn serve_clipboard(timeout: u64) -> Result<(), CMError> {
+    let data = SecretString::read_from(&mut std::io::stdin())?;
+
+    let mut stdout = std::io::stdout();
+    let started = Clipboard::new().and_then(|mut clipboard| {
+        set_secret(&mut clipboard, &data, None)?;
+        Ok(clipboard)
+    });
+    let mut clipboard = match started {
+        Ok(c) => c,
+        Err(e) => {
+            writeln!(stdout, "{}", e)?;
+            return Err(e.into());
+        }
+    };
+    writeln!(stdout, "{}", READY)?;
+    stdout.flush()?;
+
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    set_secret(&mut clipboard, &data, Some(deadline))?;
+
+    let current = clipboard.get_text().ok().map(SecretString::new);
+    if current.as_ref() == Some(&data) {
+        clipboard.clear()?;
+    }
+    Ok(())
+}
+
+/// Puts a secret on the clipboard with a hint that keeps desktop clipboard managers from adding it
+/// to their history. With a deadline it returns at the deadline or as soon as something else is
+/// copied.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn set_secret(
+    clipboard: &mut Clipboard,
+    data: &SecretString,
+    deadline: Option<Instant>,
+) -> Result<(), arboard::Error> {
+    use arboard::SetExtLinux;
+
+    let mut set = clipboard.set().exclude_from_history();
+    if let Some(deadline) = deadline {
+        set = set.wait_until(deadline);
+    }
+    set.text(data.expose())
+}
+
+/// Puts a secret on the clipboard. With a deadline it waits until then, since other systems keep
+/// the clipboard contents after the process that copied them has exited.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn set_secret(
+    clipboard: &mut Clipboard,
+    data: &SecretString,
+    deadline: Option<Instant>,
+) -> Result<(), arboard::Error> {
+    match deadline {
+        Some(deadline) => sleep(deadline.saturating_duration_since(Instant::now())),
+        None => clipboard.set_text(data.expose())?,
+    }
+    Ok(())
+}
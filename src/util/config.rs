@@ -0,0 +1,412 @@
+use crate::util::argparser::{OutputFormat, SecretType};
+use crate::util::errors::{CMError, CustomError};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::env::{home_dir, var_os};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const CONFIG_ENV_VAR: &str = "CMAN_CONFIG";
+
+/// The settings that can be read and changed with cman config.
+pub const CONFIG_KEYS: &[&str] = &[
+    "default_vault",
+    "db_path",
+    "keyfile",
+    "secret_type",
+    "output",
+    "remote_url",
+    "clip_timeout",
+    "generator.length",
+    "generator.symbols",
+    "generator.uppercase",
+    "generator.lowercase",
+    "generator.digits",
+    "generator.min_uppercase",
+    "generator.min_lowercase",
+    "generator.min_digits",
+    "generator.min_symbols",
+    "generator.exclude",
+    "generator.avoid_ambiguous",
+    "generator.alphabet",
+    "generator.passphrase",
+    "generator.words",
+    "generator.separator",
+    "generator.capitalize",
+    "generator.add_digit",
+    "generator.add_symbol",
+];
+
+static SETTINGS: OnceLock<Config> = OnceLock::new();
+
+/// The settings cman reads from its config file, $XDG_CONFIG_HOME/cman/config.toml or
+/// $HOME/.config/cman/config.toml.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Config {
+    /// The vault used when --vault is not given and $CMAN_DBFILE is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_vault: Option<String>,
+
+    /// The database used when there is no default vault instead of $HOME/.creds.db.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_path: Option<String>,
+
+    /// The keyfile of the database used when no vault is selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<String>,
+
+    /// The secret type assumed when --type is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_type: Option<SecretType>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+
+    /// The url cman pull falls back to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+
+    /// Seconds after which a copied secret is cleared from the clipboard. 0 keeps it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip_timeout: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "GeneratorConfig::is_empty")]
+    pub generator: GeneratorConfig,
+
+    #[serde(default)]
+    pub vaults: BTreeMap<String, Vault>,
+}
+
+/// How passwords are generated when the user does not give one.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GeneratorConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uppercase: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lowercase: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digits: Option<bool>,
+
+    /// The least number of characters a password has from each class.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_uppercase: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_lowercase: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_digits: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_symbols: Option<usize>,
+
+    /// Characters that never appear in a password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<String>,
+
+    /// Leave out characters that look alike, such as 0 and O.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avoid_ambiguous: Option<bool>,
+
+    /// The characters passwords are drawn from instead of the classes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alphabet: Option<String>,
+
+    /// Generate passphrases of words instead of passwords.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<bool>,
+
+    /// The number of words in a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<usize>,
+
+    /// What goes between the words of a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+
+    /// Start every word of a passphrase with a capital letter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capitalize: Option<bool>,
+
+    /// Add a digit to a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add_digit: Option<bool>,
+
+    /// Add a symbol to a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add_symbol: Option<bool>,
+}
+
+/// A named credential database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Vault {
+    pub path: String,
+
+    /// The remote url cman pull fetches this vault from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// The keyfile that unlocks this vault together with, or instead of, the master password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<String>,
+}
+
+impl Config {
+    /// Reads the config file. A missing file is the same as an empty config.
+    pub fn load() -> Result<Self, CMError> {
+        let path = config_path()?;
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        toml::from_str(&contents).map_err(|e| {
+            CustomError::new(&format!(
+                "Could not read the config file {}: {}",
+                path.display(),
+                e
+            ))
+            .into()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), CMError> {
+        let path = config_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = toml::to_string_pretty(self).map_err(|e| {
+            CMError::from(CustomError::new(&format!(
+                "Could not write the config file: {}",
+                e
+            )))
+        })?;
+        std::fs::write(&path, contents)?;
+        Ok(())
+    }
+
+    pub fn get_vault(&self, name: &str) -> Result<&Vault, CMError> {
+        self.vaults.get(name).ok_or_else(|| {
+            CustomError::new(&format!(
+                "There is no vault named {}. Register it with cman vault add {} <path>",
+                name, name
+            ))
+            .into()
+        })
+    }
+
+    /// The default vault if one is set.
+    pub fn default_vault(&self) -> Result<Option<&Vault>, CMError> {
+        match &self.default_vault {
+            Some(name) => Ok(Some(self.get_vault(name)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The value of a setting as it is shown by cman config, or None if it is not set.
+    pub fn get_key(&self, key: &str) -> Result<Option<String>, CMError> {
+        let value = match key {
+            "default_vault" => self.default_vault.clone(),
+            "db_path" => self.db_path.clone(),
+            "keyfile" => self.keyfile.clone(),
+            "secret_type" => self.secret_type.map(|t| value_name(&t)),
+            "output" => self.output.map(|o| value_name(&o)),
+            "remote_url" => self.remote_url.clone(),
+            "clip_timeout" => self.clip_timeout.map(|t| t.to_string()),
+            "generator.length" => self.generator.length.map(|l| l.to_string()),
+            "generator.symbols" => self.generator.symbols.map(|s| s.to_string()),
+            "generator.uppercase" => self.generator.uppercase.map(|s| s.to_string()),
+            "generator.lowercase" => self.generator.lowercase.map(|s| s.to_string()),
+            "generator.digits" => self.generator.digits.map(|s| s.to_string()),
+            "generator.min_uppercase" => self.generator.min_uppercase.map(|m| m.to_string()),
+            "generator.min_lowercase" => self.generator.min_lowercase.map(|m| m.to_string()),
+            "generator.min_digits" => self.generator.min_digits.map(|m| m.to_string()),
+            "generator.min_symbols" => self.generator.min_symbols.map(|m| m.to_string()),
+            "generator.exclude" => self.generator.exclude.clone(),
+            "generator.avoid_ambiguous" => self.generator.avoid_ambiguous.map(|a| a.to_string()),
+            "generator.alphabet" => self.generator.alphabet.clone(),
+            "generator.passphrase" => self.generator.passphrase.map(|p| p.to_string()),
+            "generator.words" => self.generator.words.map(|w| w.to_string()),
+            "generator.separator" => self.generator.separator.clone(),
+            "generator.capitalize" => self.generator.capitalize.map(|c| c.to_string()),
+            "generator.add_digit" => self.generator.add_digit.map(|a| a.to_string()),
+            "generator.add_symbol" => self.generator.add_symbol.map(|a| a.to_string()),
+            _ => return Err(unknown_key(key)),
+        };
+        Ok(value)
+    }
+
+    pub fn set_key(&mut self, key: &str, value: &str) -> Result<(), CMError> {
+        match key {
+            "default_vault" => {
+                self.get_vault(value)?;
+                self.default_vault = Some(value.to_string());
+            }
+            "db_path" => self.db_path = Some(value.to_string()),
+            "keyfile" => self.keyfile = Some(value.to_string()),
+            "secret_type" => self.secret_type = Some(parse_value_enum(key, value)?),
+            "output" => self.output = Some(parse_value_enum(key, value)?),
+            "remote_url" => self.remote_url = Some(value.to_string()),
+            "clip_timeout" => self.clip_timeout = Some(parse_setting(key, value)?),
+            "generator.length" => self.generator.length = Some(parse_setting(key, value)?),
+            "generator.symbols" => self.generator.symbols = Some(parse_setting(key, value)?),
+            "generator.uppercase" => self.generator.uppercase = Some(parse_setting(key, value)?),
+            "generator.lowercase" => self.generator.lowercase = Some(parse_setting(key, value)?),
+            "generator.digits" => self.generator.digits = Some(parse_setting(key, value)?),
+            "generator.min_uppercase" => {
+                self.generator.min_uppercase = Some(parse_setting(key, value)?)
+            }
+            "generator.min_lowercase" => {
+                self.generator.min_lowercase = Some(parse_setting(key, value)?)
+            }
+            "generator.min_digits" => self.generator.min_digits = Some(parse_setting(key, value)?),
+            "generator.min_symbols" => {
+                self.generator.min_symbols = Some(parse_setting(key, value)?)
+            }
+            "generator.exclude" => self.generator.exclude = Some(value.to_string()),
+            "generator.avoid_ambiguous" => {
+                self.generator.avoid_ambiguous = Some(parse_setting(key, value)?)
+            }
+            "generator.alphabet" => self.generator.alphabet = Some(value.to_string()),
+            "generator.passphrase" => self.generator.passphrase = Some(parse_setting(key, value)?),
+            "generator.words" => self.generator.words = Some(parse_setting(key, value)?),
+            "generator.separator" => self.generator.separator = Some(value.to_string()),
+            "generator.capitalize" => self.generator.capitalize = Some(parse_setting(key, value)?),
+            "generator.add_digit" => self.generator.add_digit = Some(parse_setting(key, value)?),
+            "generator.add_symbol" => self.generator.add_symbol = Some(parse_setting(key, value)?),
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    pub fn unset_key(&mut self, key: &str) -> Result<(), CMError> {
+        match key {
+            "default_vault" => self.default_vault = None,
+            "db_path" => self.db_path = None,
+            "keyfile" => self.keyfile = None,
+            "secret_type" => self.secret_type = None,
+            "output" => self.output = None,
+            "remote_url" => self.remote_url = None,
+            "clip_timeout" => self.clip_timeout = None,
+            "generator.length" => self.generator.length = None,
+            "generator.symbols" => self.generator.symbols = None,
+            "generator.uppercase" => self.generator.uppercase = None,
+            "generator.lowercase" => self.generator.lowercase = None,
+            "generator.digits" => self.generator.digits = None,
+            "generator.min_uppercase" => self.generator.min_uppercase = None,
+            "generator.min_lowercase" => self.generator.min_lowercase = None,
+            "generator.min_digits" => self.generator.min_digits = None,
+            "generator.min_symbols" => self.generator.min_symbols = None,
+            "generator.exclude" => self.generator.exclude = None,
+            "generator.avoid_ambiguous" => self.generator.avoid_ambiguous = None,
+            "generator.alphabet" => self.generator.alphabet = None,
+            "generator.passphrase" => self.generator.passphrase = None,
+            "generator.words" => self.generator.words = None,
+            "generator.separator" => self.generator.separator = None,
+            "generator.capitalize" => self.generator.capitalize = None,
+            "generator.add_digit" => self.generator.add_digit = None,
+            "generator.add_symbol" => self.generator.add_symbol = None,
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+}
+
+impl GeneratorConfig {
+    fn is_empty(&self) -> bool {
+        self.length.is_none()
+            && self.symbols.is_none()
+            && self.uppercase.is_none()
+            && self.lowercase.is_none()
+            && self.digits.is_none()
+            && self.min_uppercase.is_none()
+            && self.min_lowercase.is_none()
+            && self.min_digits.is_none()
+            && self.min_symbols.is_none()
+            && self.exclude.is_none()
+            && self.avoid_ambiguous.is_none()
+            && self.alphabet.is_none()
+            && self.passphrase.is_none()
+            && self.words.is_none()
+            && self.separator.is_none()
+            && self.capitalize.is_none()
+            && self.add_digit.is_none()
+            && self.add_symbol.is_none()
+    }
+}
+
+/// Makes the config, with any overrides from the command line applied, the settings returned by
+/// settings(). Only the first call has an effect.
+pub fn init_settings(config: Config) {
+    let _ = SETTINGS.set(config);
+}
+
+/// The settings cman runs with. Without a call to init_settings these are the built in defaults.
+pub fn settings() -> &'static Config {
+    SETTINGS.get_or_init(Config::default)
+}
+
+/// The path of the config file. $CMAN_CONFIG overrides the default location.
+pub fn config_path() -> Result<PathBuf, CMError> {
+    if let Some(path) = non_empty_env(CONFIG_ENV_VAR) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let config_dir = match non_empty_env("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match home_dir() {
+            Some(home) => home.join(".config"),
+            None => {
+                return Err(CustomError::new("Could not get the config file path").into());
+            }
+        },
+    };
+    Ok(config_dir.join("cman").join("config.toml"))
+}
+
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn parse_value_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T, CMError> {
+    T::from_str(value, true).map_err(|_| {
+        let names: Vec<String> = T::value_variants().iter().map(value_name).collect();
+        CustomError::new(&format!("{} should be one of {}", key, names.join(", "))).into()
+    })
+}
+
+fn parse_setting<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, CMError> {
+    value.parse().map_err(|_| {
+        CustomError::new(&format!("{} is not a valid value for {}", value, key)).into()
+    })
+}
+
+fn unknown_key(key: &str) -> CMError {
+    CustomError::new(&format!(
+        "Unknown setting {}. The settings are {}",
+        key,
+        CONFIG_KEYS.join(", ")
+    ))
+    .into()
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    var_os(name)
+        .and_then(|v| v.into_string().ok())
+        .filter(|v| !v.is_empty())
+}
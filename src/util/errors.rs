@@ -30,6 +30,7 @@ pub enum CMError {
     InquireError(inquire::InquireError),
     ClipboardError(arboard::Error),
     ReqwestError(reqwest::Error),
+    SshKeyError(ssh_key::Error),
     Custom(CustomError),
 }
 
@@ -42,6 +43,7 @@ impl fmt::Display for CMError {
             Self::InquireError(err) => write!(f, "Error: {}", err),
             Self::ReqwestError(err) => write!(f, "Error: {}", err),
             Self::ClipboardError(err) => write!(f, "Error: {}", err),
+            Self::SshKeyError(err) => write!(f, "SSH Key Error: {}", err),
         }
     }
 }
@@ -81,3 +83,9 @@ impl From<reqwest::Error> for CMError {
         CMError::ReqwestError(value)
     }
 }
+
+impl From<ssh_key::Error> for CMError {
+    fn from(value: ssh_key::Error) -> Self {
+        CMError::SshKeyError(value)
+    }
+}
@@ -1,18 +1,24 @@
 use crate::objects::Secret;
-use crate::util::argparser::{CmanArgs, Commands, FieldType, GetArgs, SecretType};
-use crate::util::errors::CMError;
+use crate::util::argparser::{CmanArgs, Commands, GetArgs};
+use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
 
 use inquire::*;
+use rand::TryRngCore;
+use rand::rngs::OsRng;
+use zeroize::Zeroizing;
+
+use std::env::var;
 use std::fmt::Display;
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
 use std::sync::Mutex;
 use std::time::Duration;
 
 static QUIET: Mutex<bool> = Mutex::new(false);
 
-pub fn get_terminal_input(prompt: &str, confirm: bool, private: bool) -> Result<String, CMError> {
-    if private {
-        return get_private_input(prompt, confirm);
-    }
+pub fn get_terminal_input(prompt: &str) -> Result<String, CMError> {
     let prompt = &format!("{}: ", prompt);
 
     let input = Text::new(prompt).prompt()?;
@@ -46,7 +52,114 @@ where
     Ok(options)
 }
 
-fn get_private_input(prompt: &str, confirm: bool) -> Result<String, CMError> {
+/// Reads multi-line text such as the body of a note. Text piped to stdin is used as is, otherwise
+/// $VISUAL or $EDITOR is opened with `initial` already filled in.
+pub fn get_multiline_input(prompt: &str, initial: &str) -> Result<SecretString, CMError> {
+    let mut stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        let mut input = String::new();
+        stdin.read_to_string(&mut input)?;
+        return Ok(input.into());
+    }
+
+    let file = EditorFile::create(initial)?;
+    let editor = var("VISUAL")
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .or_else(|| var("EDITOR").ok().filter(|e| !e.trim().is_empty()))
+        .unwrap_or("vi".to_string());
+    // the editor can be given with arguments, ie code --wait.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    eprintln!("{} in {}", prompt, program);
+    let status = Command::new(program)
+        .args(words)
+        .arg(&file.path)
+        .status()
+        .map_err(|e| {
+            CMError::from(CustomError::new(&format!(
+                "Could not run the editor {}: {}",
+                program, e
+            )))
+        })?;
+    if !status.success() {
+        return Err(
+            CustomError::new(&format!("The editor {} exited with {}", program, status)).into(),
+        );
+    }
+    file.read()
+}
+
+/// The file a secret is edited in. It is created where only the user can read it and in memory
+/// rather than on disk, and overwritten and removed when dropped.
+struct EditorFile {
+    path: PathBuf,
+}
+
+impl EditorFile {
+    fn create(contents: &str) -> Result<Self, CMError> {
+        let Some(dir) = private_tmp_dir() else {
+            return Err(CustomError::new(
+                "There is no private temporary directory ($XDG_RUNTIME_DIR or /dev/shm) to edit the text in. Pipe it to cman instead",
+            )
+            .into());
+        };
+        let mut name = [0u8; 16];
+        OsRng
+            .try_fill_bytes(&mut name)
+            .map_err(|_| CustomError::new("Could not get random bytes for the file name"))?;
+        let name: String = name.iter().map(|b| format!("{:02x}", b)).collect();
+        let path = dir.join(format!("cman-{}.txt", name));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        let editor_file = Self { path };
+        file.write_all(contents.as_bytes())?;
+        Ok(editor_file)
+    }
+
+    fn read(&self) -> Result<SecretString, CMError> {
+        let contents = Zeroizing::new(std::fs::read(&self.path)?);
+        let text = std::str::from_utf8(&contents)
+            .map_err(|_| CustomError::new("The edited text is not valid UTF-8"))?;
+        Ok(text.into())
+    }
+}
+
+impl Drop for EditorFile {
+    fn drop(&mut self) {
+        if let Ok(len) = std::fs::metadata(&self.path).map(|m| m.len())
+            && let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(&self.path)
+        {
+            let _ = file.write_all(&vec![0u8; len as usize]);
+            let _ = file.sync_all();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A directory that is kept in memory and that other users cannot list: $XDG_RUNTIME_DIR, or
+/// /dev/shm where files are only protected by their own permissions.
+fn private_tmp_dir() -> Option<PathBuf> {
+    if !cfg!(unix) {
+        return None;
+    }
+    let runtime_dir = var("XDG_RUNTIME_DIR").ok().filter(|d| !d.is_empty());
+    runtime_dir
+        .map(PathBuf::from)
+        .into_iter()
+        .chain([PathBuf::from("/dev/shm")])
+        .find(|d| d.is_dir())
+}
+
+/// Reads a password or other secret without echoing it. With `confirm` it has to be typed twice.
+pub fn get_private_input(prompt: &str, confirm: bool) -> Result<SecretString, CMError> {
     let prompt = &format!("{}: ", prompt);
 
     let mut password = Password::new(prompt);
@@ -55,7 +168,7 @@ fn get_private_input(prompt: &str, confirm: bool) -> Result<String, CMError> {
     }
 
     let input_password = password.prompt()?;
-    Ok(input_password)
+    Ok(input_password.into())
 }
 
 pub fn get_user_confirmation(message: &str) -> Result<bool, CMError> {
@@ -73,38 +186,36 @@ pub fn print_result(field: &str, value: &str) {
 }
 
 pub fn print_secrets(secrets: &Vec<Secret>, getargs: &GetArgs) -> Result<(), CMError> {
-    let sec_type = getargs.secret_type.unwrap_or(SecretType::Login);
-    let default_field = if sec_type == SecretType::Login {
-        FieldType::Pass
-    } else {
-        FieldType::Key
-    };
-
     let quiet = shouldbequiet();
-    if !quiet {
+    // notes are printed verbatim so that their output can be redirected to a file as is.
+    let verbatim = secrets.iter().all(|s| matches!(s, Secret::Note(_)));
+    if !quiet && !verbatim {
         println!()
     }
 
     for secret in secrets {
-        if let Some(fieldtype) = getargs.field {
+        if let Some(fieldtype) = &getargs.field {
             secret.print_field(fieldtype);
             continue;
         }
         secret.print();
     }
 
-    if secrets.len() == 1 {
-        // send the password or api key to clipboard if only one was requested
-        secrets[0].send_field_to_clipboard(default_field)?;
+    if secrets.len() == 1 && !getargs.no_clip {
+        // send the password, api key or public key to clipboard if only one was requested
+        secrets[0].send_field_to_clipboard(&secrets[0].default_field())?;
     }
 
     Ok(())
 }
 
 pub fn set_terminal_settings(args: &CmanArgs) {
-    if let Commands::Get(getargs) = &args.command
-        && getargs.quiet
-    {
+    let quiet = match &args.command {
+        Commands::Get(getargs) => getargs.quiet,
+        Commands::Otp(otpargs) => otpargs.quiet,
+        _ => false,
+    };
+    if quiet {
         let guard = QUIET.lock().ok();
         if let Some(mut guard) = guard {
             *guard = true;
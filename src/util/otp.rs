@@ -0,0 +1,432 @@
+use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
+
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpKind {
+    /// Time based codes (RFC 6238) valid for `period` seconds.
+    Totp { period: u64 },
+    /// Counter based codes (RFC 4226). The counter is the one the next code is generated with.
+    Hotp { counter: u64 },
+}
+
+/// A one time password seed together with the parameters needed to generate codes from it. The
+/// seed is wiped from memory when it is dropped.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OtpSecret {
+    pub kind: OtpKind,
+    pub secret: Zeroizing<Vec<u8>>,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+    pub label: String,
+    pub issuer: Option<String>,
+}
+
+impl fmt::Debug for OtpSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OtpSecret")
+            .field("kind", &self.kind)
+            .field("secret", &"[redacted]")
+            .field("algorithm", &self.algorithm)
+            .field("digits", &self.digits)
+            .field("label", &self.label)
+            .field("issuer", &self.issuer)
+            .finish()
+    }
+}
+
+impl OtpAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        }
+    }
+}
+
+impl OtpSecret {
+    /// Parses an otpauth:// URI or a bare base32 secret. A bare secret uses the defaults most
+    /// sites use: TOTP with SHA1, 6 digits and a 30 second period.
+    pub fn parse(input: &str) -> Result<Self, CMError> {
+        let input = input.trim();
+        if !input.starts_with("otpauth://") {
+            return Ok(Self {
+                kind: OtpKind::Totp {
+                    period: DEFAULT_PERIOD,
+                },
+                secret: base32_decode(input)?,
+                algorithm: OtpAlgorithm::Sha1,
+                digits: DEFAULT_DIGITS,
+                label: String::new(),
+                issuer: None,
+            });
+        }
+
+        let rest = &input["otpauth://".len()..];
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| otp_error("The otpauth URI has no label"))?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut secret = None;
+        let mut algorithm = OtpAlgorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        let mut issuer = None;
+
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            match key.to_lowercase().as_str() {
+                "secret" => secret = Some(base32_decode(&value)?),
+                "algorithm" => {
+                    algorithm = match value.to_uppercase().as_str() {
+                        "SHA1" => OtpAlgorithm::Sha1,
+                        "SHA256" => OtpAlgorithm::Sha256,
+                        "SHA512" => OtpAlgorithm::Sha512,
+                        _ => return Err(otp_error(&format!("Unsupported algorithm {}", value))),
+                    }
+                }
+                "digits" => {
+                    digits = match value.parse() {
+                        Ok(d) if (6..=10).contains(&d) => d,
+                        _ => return Err(otp_error("digits should be a number from 6 to 10")),
+                    }
+                }
+                "period" => {
+                    period = match value.parse() {
+                        Ok(p) if p > 0 => p,
+                        _ => return Err(otp_error("period should be a positive number")),
+                    }
+                }
+                "counter" => {
+                    counter = Some(
+                        value
+                            .parse()
+                            .map_err(|_| otp_error("counter should be a positive number"))?,
+                    )
+                }
+                "issuer" => issuer = Some(value),
+                // parameters like image are not needed to generate codes.
+                _ => (),
+            }
+        }
+
+        let kind = match kind.to_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter
+                    .ok_or_else(|| otp_error("A hotp URI needs a counter parameter"))?,
+            },
+            _ => return Err(otp_error(&format!("Unsupported otp type {}", kind))),
+        };
+
+        Ok(Self {
+            kind,
+            secret: secret.ok_or_else(|| otp_error("The otpauth URI has no secret"))?,
+            algorithm,
+            digits,
+            label: percent_decode(label)?,
+            issuer,
+        })
+    }
+
+    /// The otpauth:// URI the secret is stored as.
+    pub fn to_uri(&self) -> SecretString {
+        let (kind, param) = match self.kind {
+            OtpKind::Totp { period } => ("totp", format!("period={}", period)),
+            OtpKind::Hotp { counter } => ("hotp", format!("counter={}", counter)),
+        };
+        let label = percent_encode(&self.label);
+        let secret = base32_encode(&self.secret);
+        let mut rest = format!(
+            "&algorithm={}&digits={}&{}",
+            self.algorithm.name(),
+            self.digits,
+            param
+        );
+        if let Some(issuer) = &self.issuer {
+            rest.push_str(&format!("&issuer={}", percent_encode(issuer)));
+        }
+
+        // allocated once so that growing it leaves no copies of the secret behind.
+        let prefix = format!("otpauth://{}/{}?secret=", kind, label);
+        let mut uri = String::with_capacity(prefix.len() + secret.len() + rest.len());
+        uri.push_str(&prefix);
+        uri.push_str(&secret);
+        uri.push_str(&rest);
+        uri.into()
+    }
+
+    /// Generates the code for the given counter value as described in RFC 4226.
+    pub fn generate(&self, counter: u64) -> String {
+        let message = counter.to_be_bytes();
+        let hash = match self.algorithm {
+            OtpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(&self.secret, &message),
+            OtpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(&self.secret, &message),
+            OtpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(&self.secret, &message),
+        };
+
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        let code = binary as u64 % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// Generates the TOTP code for the given unix time and how many seconds it stays valid.
+    /// HOTP secrets are generated with their stored counter and have no expiry.
+    pub fn generate_at(&self, unix_time: u64) -> (String, Option<u64>) {
+        match self.kind {
+            OtpKind::Totp { period } => (
+                self.generate(unix_time / period),
+                Some(period - unix_time % period),
+            ),
+            OtpKind::Hotp { counter } => (self.generate(counter), None),
+        }
+    }
+
+    pub fn generate_now(&self) -> (String, Option<u64>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.generate_at(now)
+    }
+}
+
+fn hmac_digest<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    // hmac accepts keys of any length so creating it cannot fail.
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn otp_error(message: &str) -> CMError {
+    CustomError::new(&format!("Invalid OTP secret: {}", message)).into()
+}
+
+/// Decodes base32 (RFC 4648) ignoring case, spaces and padding as authenticator apps do.
+fn base32_decode(input: &str) -> Result<Zeroizing<Vec<u8>>, CMError> {
+    // every character holds 5 bits, allocated up front so that no copies of the secret are left.
+    let mut bytes = Zeroizing::new(Vec::with_capacity(input.len() * 5 / 8 + 1));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())
+            .ok_or_else(|| otp_error(&format!("'{}' is not a base32 character", c)))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(otp_error("The secret is empty"));
+    }
+    Ok(bytes)
+}
+
+fn base32_encode(bytes: &[u8]) -> Zeroizing<String> {
+    let mut output = Zeroizing::new(String::with_capacity(bytes.len().div_ceil(5) * 8));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+fn percent_decode(input: &str) -> Result<String, CMError> {
+    let mut bytes = Vec::new();
+    let mut iter = input.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let hex: Vec<u8> = iter.by_ref().take(2).collect();
+                let hex = std::str::from_utf8(&hex).unwrap_or("");
+                let value = u8::from_str_radix(hex, 16)
+                    .map_err(|_| otp_error("Bad percent encoding in the otpauth URI"))?;
+                bytes.push(value);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| otp_error("The otpauth URI is not valid UTF-8"))
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1_SEED: &[u8] = b"12345678901234567890";
+    const SHA256_SEED: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SEED: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    fn totp(seed: &[u8], algorithm: &str) -> OtpSecret {
+        OtpSecret::parse(&format!(
+            "otpauth://totp/test?secret={}&algorithm={}&digits=8&period=30",
+            *base32_encode(seed),
+            algorithm
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn rfc6238_vectors() {
+        // RFC 6238 appendix B: time, SHA1, SHA256, SHA512.
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        let sha1 = totp(SHA1_SEED, "SHA1");
+        let sha256 = totp(SHA256_SEED, "SHA256");
+        let sha512 = totp(SHA512_SEED, "SHA512");
+        for (time, code1, code256, code512) in vectors {
+            assert_eq!(sha1.generate_at(time).0, code1, "SHA1 at {}", time);
+            assert_eq!(sha256.generate_at(time).0, code256, "SHA256 at {}", time);
+            assert_eq!(sha512.generate_at(time).0, code512, "SHA512 at {}", time);
+        }
+    }
+
+    #[test]
+    fn totp_remaining_seconds() {
+        let secret = totp(SHA1_SEED, "SHA1");
+        assert_eq!(secret.generate_at(59).1, Some(1));
+        assert_eq!(secret.generate_at(60).1, Some(30));
+    }
+
+    #[test]
+    fn rfc4226_vectors() {
+        // RFC 4226 appendix D.
+        let codes = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in codes.iter().enumerate() {
+            let secret = OtpSecret::parse(&format!(
+                "otpauth://hotp/test?secret={}&counter={}",
+                *base32_encode(SHA1_SEED),
+                counter
+            ))
+            .unwrap();
+            assert_eq!(secret.generate(counter as u64), *code);
+            assert_eq!(secret.generate_at(0), (code.to_string(), None));
+        }
+    }
+
+    #[test]
+    fn bare_secret_uses_defaults() {
+        let secret = OtpSecret::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(*secret.secret, SHA1_SEED);
+        assert_eq!(secret.kind, OtpKind::Totp { period: 30 });
+        assert_eq!(secret.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!(secret.digits, 6);
+    }
+
+    #[test]
+    fn uri_round_trip() {
+        let uris = [
+            "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example",
+            "otpauth://totp/bank?secret=JBSWY3DPEHPK3PXP&algorithm=SHA256&digits=8&period=60",
+            "otpauth://totp/work%20vpn?secret=JBSWY3DPEHPK3PXP&algorithm=SHA512&digits=10&period=15",
+            "otpauth://hotp/token?secret=JBSWY3DPEHPK3PXP&counter=42&digits=7",
+        ];
+        for uri in uris {
+            let secret = OtpSecret::parse(uri).unwrap();
+            let reparsed = OtpSecret::parse(secret.to_uri().expose()).unwrap();
+            assert_eq!(secret, reparsed, "{}", uri);
+        }
+
+        let secret = OtpSecret::parse(uris[2]).unwrap();
+        assert_eq!(secret.kind, OtpKind::Totp { period: 15 });
+        assert_eq!(secret.algorithm, OtpAlgorithm::Sha512);
+        assert_eq!(secret.digits, 10);
+        assert_eq!(secret.label, "work vpn");
+
+        let secret = OtpSecret::parse(uris[0]).unwrap();
+        assert_eq!(secret.issuer.as_deref(), Some("Example"));
+        assert_eq!(secret.label, "Example:alice@example.com");
+    }
+
+    #[test]
+    fn rejects_bad_uris() {
+        let uris = [
+            "",
+            "not base32!",
+            "otpauth://totp",
+            "otpauth://totp/test",
+            "otpauth://totp/test?secret=",
+            "otpauth://totp/test?secret=JBSWY3DP1",
+            "otpauth://totp/test?secret=JBSWY3DP&algorithm=MD5",
+            "otpauth://totp/test?secret=JBSWY3DP&digits=5",
+            "otpauth://totp/test?secret=JBSWY3DP&digits=11",
+            "otpauth://totp/test?secret=JBSWY3DP&period=0",
+            "otpauth://hotp/test?secret=JBSWY3DP",
+            "otpauth://hotp/test?secret=JBSWY3DP&counter=-1",
+            "otpauth://motp/test?secret=JBSWY3DP",
+            "otpauth://totp/test%zz?secret=JBSWY3DP",
+        ];
+        for uri in uris {
+            assert!(OtpSecret::parse(uri).is_err(), "{} was accepted", uri);
+        }
+    }
+}
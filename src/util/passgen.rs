@@ -1,43 +1,683 @@
+use crate::util::config::{GeneratorConfig, settings};
 use crate::util::errors::{CMError, CustomError};
+use crate::util::secret::SecretString;
 
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
+use zeroize::Zeroize;
 
 const MAX_PASSLEN: usize = 255;
 const DEFAULT_PASSLEN: usize = 16;
+const UPPERCASE_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWERCASE_CHARS: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGIT_CHARS: &str = "1234567890";
+const SYMBOL_CHARS: &str = "!@#$%^&*()";
+/// Characters that are easily mistaken for one another when a password is read or typed.
+const AMBIGUOUS_CHARS: &str = "0Oo1Il|";
+/// Short common words, one per line. There are 6^4 of them so that a word can also be picked with
+/// four dice.
+const WORDLIST: &str = include_str!("wordlist.txt");
+const MAX_WORDS: usize = 64;
+const DEFAULT_WORDS: usize = 6;
+const DEFAULT_SEPARATOR: &str = "-";
 
-pub fn get_random_pass(passlen: Option<usize>) -> Result<String, CMError> {
-    let passlen = passlen.unwrap_or(DEFAULT_PASSLEN);
-    if passlen > MAX_PASSLEN {
-        return Err(CustomError::new(&format!(
-            "Password length provided is above the upper limit of {} characters",
-            MAX_PASSLEN
-        ))
-        .into());
+/// A group of characters a policy can include and ask a minimum number of.
+struct CharClass {
+    name: &'static str,
+    chars: &'static str,
+    included: Option<bool>,
+    min: Option<usize>,
+    member: fn(&char) -> bool,
+}
+
+/// What a generated password is made of: the characters it is drawn from and how many characters
+/// of a class it has to contain at least.
+pub struct PasswordPolicy {
+    length: usize,
+    alphabet: Vec<char>,
+    minimums: Vec<(Vec<char>, usize)>,
+}
+
+impl PasswordPolicy {
+    /// Builds the policy from the generator settings. Without an explicit length the
+    /// generator.length setting is used.
+    pub fn from_settings(passlen: Option<usize>) -> Result<Self, CMError> {
+        let generator = &settings().generator;
+        let length = passlen.or(generator.length).unwrap_or(DEFAULT_PASSLEN);
+        if length > MAX_PASSLEN {
+            return Err(CustomError::new(&format!(
+                "Password length provided is above the upper limit of {} characters",
+                MAX_PASSLEN
+            ))
+            .into());
+        }
+        Self::new(length, generator)
+    }
+
+    fn new(length: usize, generator: &GeneratorConfig) -> Result<Self, CMError> {
+        if length == 0 {
+            return Err(CustomError::new("The password length has to be at least 1").into());
+        }
+        let classes = [
+            CharClass {
+                name: "uppercase",
+                chars: UPPERCASE_CHARS,
+                included: generator.uppercase,
+                min: generator.min_uppercase,
+                member: char::is_ascii_uppercase,
+            },
+            CharClass {
+                name: "lowercase",
+                chars: LOWERCASE_CHARS,
+                included: generator.lowercase,
+                min: generator.min_lowercase,
+                member: char::is_ascii_lowercase,
+            },
+            CharClass {
+                name: "digits",
+                chars: DIGIT_CHARS,
+                included: generator.digits,
+                min: generator.min_digits,
+                member: char::is_ascii_digit,
+            },
+            CharClass {
+                name: "symbols",
+                chars: SYMBOL_CHARS,
+                included: generator.symbols,
+                min: generator.min_symbols,
+                member: char::is_ascii_punctuation,
+            },
+        ];
+
+        let mut excluded: String = generator.exclude.clone().unwrap_or_default();
+        if generator.avoid_ambiguous.unwrap_or(false) {
+            excluded.push_str(AMBIGUOUS_CHARS);
+        }
+
+        // a class asked for with a minimum is included even if the setting leaves it out. A custom
+        // alphabet replaces the classes, which then only pick out its characters for the minimums.
+        let source: String = match &generator.alphabet {
+            Some(alphabet) => alphabet.clone(),
+            None => classes
+                .iter()
+                .filter(|c| c.included.unwrap_or(true) || c.min.unwrap_or(0) > 0)
+                .map(|c| c.chars)
+                .collect(),
+        };
+        let mut alphabet: Vec<char> = Vec::new();
+        for c in source.chars() {
+            // every character appears once so that none is more likely than the others.
+            if !excluded.contains(c) && !alphabet.contains(&c) {
+                alphabet.push(c);
+            }
+        }
+        if alphabet.is_empty() {
+            return Err(
+                CustomError::new("No characters are left to generate a password from").into(),
+            );
+        }
+
+        let mut minimums = Vec::new();
+        let mut defaults = Vec::new();
+        for class in &classes {
+            let chars: Vec<char> = alphabet
+                .iter()
+                .copied()
+                .filter(|c| (class.member)(c))
+                .collect();
+            match class.min {
+                Some(0) => {}
+                Some(min) => {
+                    if chars.is_empty() {
+                        return Err(CustomError::new(&format!(
+                            "No {} are left to satisfy generator.min_{}",
+                            class.name, class.name
+                        ))
+                        .into());
+                    }
+                    minimums.push((chars, min));
+                }
+                // without a custom alphabet every class used is present at least once.
+                None => {
+                    if generator.alphabet.is_none() && !chars.is_empty() {
+                        defaults.push((chars, 1));
+                    }
+                }
+            }
+        }
+
+        let required: usize = minimums.iter().map(|(_, min)| min).sum();
+        if required > length {
+            return Err(CustomError::new(&format!(
+                "The minimum counts add up to {} characters, more than the password length of {}",
+                required, length
+            ))
+            .into());
+        }
+        // the default of one character per class is dropped for passwords too short to fit it.
+        if required + defaults.len() <= length {
+            minimums.extend(defaults);
+        }
+
+        Ok(Self {
+            length,
+            alphabet,
+            minimums,
+        })
+    }
+
+    /// Generates a password. The characters required by the minimums are drawn from their class
+    /// first, the rest from the whole alphabet, and the result is shuffled.
+    pub fn generate(&self) -> Result<SecretString, CMError> {
+        let mut rng = match ChaCha20Rng::try_from_os_rng() {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(CustomError::new(&format!("Error generating Password: {}", e)).into());
+            }
+        };
+
+        let mut chars: Vec<char> = Vec::with_capacity(self.length);
+        for (class, min) in &self.minimums {
+            for _ in 0..*min {
+                chars.push(class[random_below(&mut rng, class.len())]);
+            }
+        }
+        while chars.len() < self.length {
+            chars.push(self.alphabet[random_below(&mut rng, self.alphabet.len())]);
+        }
+        // Fisher-Yates, so that the required characters can end up anywhere.
+        for i in (1..chars.len()).rev() {
+            chars.swap(i, random_below(&mut rng, i + 1));
+        }
+
+        // allocated once so that no partial copies of the password are left behind when it grows.
+        let mut pass = String::with_capacity(chars.iter().map(|c| c.len_utf8()).sum());
+        pass.extend(chars.iter());
+        chars.zeroize();
+
+        Ok(pass.into())
+    }
+
+    /// The bits of entropy of a generated password. The characters required by the minimums only
+    /// count with the size of their class and the shuffle is left out, so this is a lower bound.
+    pub fn entropy(&self) -> f64 {
+        let mut remaining = self.length;
+        let mut bits = 0.0;
+        for (class, min) in &self.minimums {
+            bits += *min as f64 * (class.len() as f64).log2();
+            remaining -= min;
+        }
+        bits + remaining as f64 * (self.alphabet.len() as f64).log2()
+    }
+}
+
+/// What a generated passphrase is made of.
+pub struct PassphrasePolicy {
+    words: usize,
+    separator: String,
+    capitalize: bool,
+    add_digit: bool,
+    add_symbol: bool,
+}
+
+impl PassphrasePolicy {
+    /// Builds the policy from the generator settings.
+    pub fn from_settings() -> Result<Self, CMError> {
+        let generator = &settings().generator;
+        let words = generator.words.unwrap_or(DEFAULT_WORDS);
+        if words == 0 || words > MAX_WORDS {
+            return Err(CustomError::new(&format!(
+                "A passphrase has between 1 and {} words",
+                MAX_WORDS
+            ))
+            .into());
+        }
+        Ok(Self {
+            words,
+            separator: generator
+                .separator
+                .clone()
+                .unwrap_or(DEFAULT_SEPARATOR.to_string()),
+            capitalize: generator.capitalize.unwrap_or(false),
+            add_digit: generator.add_digit.unwrap_or(false),
+            add_symbol: generator.add_symbol.unwrap_or(false),
+        })
     }
-    let chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz1234567890!@#$%^&*()";
-    let chars_arr: Vec<char> = chars.chars().collect();
-    let chars_size = chars.len();
 
-    let mut pass = String::new();
-    let mut index_buff: Vec<u8> = vec![0; passlen];
+    /// Generates a passphrase of words picked from the word list. The digit and symbol are added
+    /// to the end of a random word.
+    pub fn generate(&self) -> Result<SecretString, CMError> {
+        let mut rng = match ChaCha20Rng::try_from_os_rng() {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(
+                    CustomError::new(&format!("Error generating Passphrase: {}", e)).into(),
+                );
+            }
+        };
+        let wordlist: Vec<&str> = WORDLIST.lines().collect();
+        let longest = wordlist.iter().map(|w| w.len()).max().unwrap_or(0);
+
+        let mut indexes: Vec<usize> = (0..self.words)
+            .map(|_| random_below(&mut rng, wordlist.len()))
+            .collect();
+        let digits: Vec<char> = DIGIT_CHARS.chars().collect();
+        let symbols: Vec<char> = SYMBOL_CHARS.chars().collect();
+        let mut extras: Vec<(usize, char)> = Vec::new();
+        if self.add_digit {
+            extras.push((
+                random_below(&mut rng, self.words),
+                digits[random_below(&mut rng, digits.len())],
+            ));
+        }
+        if self.add_symbol {
+            extras.push((
+                random_below(&mut rng, self.words),
+                symbols[random_below(&mut rng, symbols.len())],
+            ));
+        }
+
+        // allocated once so that no partial copies of the passphrase are left behind when it grows.
+        let mut phrase =
+            String::with_capacity(self.words * (longest + self.separator.len() + extras.len()));
+        for (i, index) in indexes.iter().enumerate() {
+            if i > 0 {
+                phrase.push_str(&self.separator);
+            }
+            let mut chars = wordlist[*index].chars();
+            if self.capitalize
+                && let Some(first) = chars.next()
+            {
+                phrase.push(first.to_ascii_uppercase());
+            }
+            phrase.extend(chars);
+            for (_, extra) in extras.iter().filter(|(word, _)| *word == i) {
+                phrase.push(*extra);
+            }
+        }
+        indexes.zeroize();
+        for (word, extra) in extras.iter_mut() {
+            word.zeroize();
+            extra.zeroize();
+        }
+
+        Ok(phrase.into())
+    }
 
-    let mut rng = match ChaCha20Rng::try_from_os_rng() {
-        Ok(r) => r,
-        Err(e) => {
-            return Err(CustomError::new(&format!("Error generating Password: {}", e)).into());
+    /// The bits of entropy of a generated passphrase. Where the digit and symbol end up is not
+    /// counted, so this is a lower bound.
+    pub fn entropy(&self) -> f64 {
+        let mut bits = self.words as f64 * (WORDLIST.lines().count() as f64).log2();
+        if self.add_digit {
+            bits += (DIGIT_CHARS.len() as f64).log2();
         }
-    };
-    rng.fill_bytes(&mut index_buff);
+        if self.add_symbol {
+            bits += (SYMBOL_CHARS.len() as f64).log2();
+        }
+        bits
+    }
+}
+
+/// A password or passphrase generator, whichever the generator.passphrase setting asks for.
+pub enum Generator {
+    Password(PasswordPolicy),
+    Passphrase(PassphrasePolicy),
+}
 
-    for i in index_buff.iter() {
-        let mut index: usize = *i as usize;
+impl Generator {
+    /// Builds the generator from the generator settings. An explicit length always asks for a
+    /// password.
+    pub fn from_settings(passlen: Option<usize>) -> Result<Self, CMError> {
+        if passlen.is_none() && settings().generator.passphrase.unwrap_or(false) {
+            Ok(Self::Passphrase(PassphrasePolicy::from_settings()?))
+        } else {
+            Ok(Self::Password(PasswordPolicy::from_settings(passlen)?))
+        }
+    }
 
-        index %= chars_size;
-        let passchar = &chars_arr[index];
+    pub fn generate(&self) -> Result<SecretString, CMError> {
+        match self {
+            Self::Password(p) => p.generate(),
+            Self::Passphrase(p) => p.generate(),
+        }
+    }
 
-        pass.push(*passchar);
+    pub fn entropy(&self) -> f64 {
+        match self {
+            Self::Password(p) => p.entropy(),
+            Self::Passphrase(p) => p.entropy(),
+        }
     }
+}
 
-    Ok(pass)
+/// Generates a password, or a passphrase if the generator.passphrase setting is on, following the
+/// generator settings. Without an explicit length the generator.length setting is used.
+pub fn get_random_pass(passlen: Option<usize>) -> Result<SecretString, CMError> {
+    Generator::from_settings(passlen)?.generate()
+}
+
+/// A uniformly distributed number below `n`. Random values from the incomplete range at the top of
+/// u32 are thrown away since taking them modulo `n` would make the low numbers more likely.
+fn random_below(rng: &mut ChaCha20Rng, n: usize) -> usize {
+    let n = n as u32;
+    let limit = u32::MAX - (u32::MAX - n + 1) % n;
+    loop {
+        let value = rng.next_u32();
+        if value <= limit {
+            return (value % n) as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pearson's chi-square statistic of observed counts against a uniform distribution.
+    fn chi_square(counts: &[u64]) -> f64 {
+        let total: u64 = counts.iter().sum();
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|c| (*c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    fn new_policy(length: usize, generator: GeneratorConfig) -> PasswordPolicy {
+        PasswordPolicy::new(length, &generator).unwrap()
+    }
+
+    fn passwords(policy: &PasswordPolicy, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| policy.generate().unwrap().expose().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn random_below_is_uniform() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        // 72 does not divide 2^32, so a plain modulo would favour the low values.
+        for n in [3, 10, 72, 1296] {
+            let mut counts = vec![0u64; n];
+            for _ in 0..n * 2000 {
+                counts[random_below(&mut rng, n)] += 1;
+            }
+            // the 99.99th percentile of the chi-square distribution with n - 1 degrees of freedom
+            // is below this bound for all of these n.
+            let df = (n - 1) as f64;
+            let bound = df + 8.0 * (2.0 * df).sqrt() + 20.0;
+            let statistic = chi_square(&counts);
+            assert!(statistic < bound, "n = {}: {} >= {}", n, statistic, bound);
+        }
+    }
+
+    #[test]
+    fn random_below_stays_in_range() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        for n in 1..200 {
+            for _ in 0..100 {
+                assert!(random_below(&mut rng, n) < n);
+            }
+        }
+    }
+
+    #[test]
+    fn small_alphabet_is_uniform() {
+        let policy = new_policy(
+            10,
+            GeneratorConfig {
+                alphabet: Some("abcde".to_string()),
+                ..Default::default()
+            },
+        );
+        let mut counts = [0u64; 5];
+        for pass in passwords(&policy, 5000) {
+            for c in pass.chars() {
+                counts[(c as u8 - b'a') as usize] += 1;
+            }
+        }
+        // four degrees of freedom, p < 0.0001.
+        let statistic = chi_square(&counts);
+        assert!(statistic < 23.5, "{:?} gives {}", counts, statistic);
+    }
+
+    #[test]
+    fn required_characters_are_spread_over_the_password() {
+        let policy = new_policy(
+            8,
+            GeneratorConfig {
+                min_digits: Some(2),
+                ..Default::default()
+            },
+        );
+        let mut counts = [0u64; 8];
+        for pass in passwords(&policy, 5000) {
+            for (i, c) in pass.chars().enumerate() {
+                if c.is_ascii_digit() {
+                    counts[i] += 1;
+                }
+            }
+        }
+        // seven degrees of freedom, p < 0.0001.
+        let statistic = chi_square(&counts);
+        assert!(statistic < 29.9, "{:?} gives {}", counts, statistic);
+    }
+
+    #[test]
+    fn minimums_are_met() {
+        let policy = new_policy(
+            12,
+            GeneratorConfig {
+                min_uppercase: Some(3),
+                min_lowercase: Some(2),
+                min_digits: Some(4),
+                min_symbols: Some(3),
+                ..Default::default()
+            },
+        );
+        for pass in passwords(&policy, 2000) {
+            let count = |member: fn(&char) -> bool| pass.chars().filter(member).count();
+            assert_eq!(pass.chars().count(), 12);
+            assert!(count(char::is_ascii_uppercase) >= 3, "{}", pass);
+            assert!(count(char::is_ascii_lowercase) >= 2, "{}", pass);
+            assert!(count(char::is_ascii_digit) >= 4, "{}", pass);
+            assert!(count(|c| !c.is_ascii_alphanumeric()) >= 3, "{}", pass);
+        }
+    }
+
+    #[test]
+    fn every_class_appears_by_default() {
+        let policy = new_policy(4, GeneratorConfig::default());
+        for pass in passwords(&policy, 2000) {
+            assert!(pass.chars().any(|c| c.is_ascii_uppercase()), "{}", pass);
+            assert!(pass.chars().any(|c| c.is_ascii_lowercase()), "{}", pass);
+            assert!(pass.chars().any(|c| c.is_ascii_digit()), "{}", pass);
+            assert!(pass.chars().any(|c| SYMBOL_CHARS.contains(c)), "{}", pass);
+        }
+    }
+
+    #[test]
+    fn left_out_classes_never_appear() {
+        let policy = new_policy(
+            32,
+            GeneratorConfig {
+                symbols: Some(false),
+                uppercase: Some(false),
+                ..Default::default()
+            },
+        );
+        for pass in passwords(&policy, 500) {
+            assert!(
+                pass.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()),
+                "{}",
+                pass
+            );
+        }
+    }
+
+    #[test]
+    fn excluded_characters_never_appear() {
+        let policy = new_policy(
+            32,
+            GeneratorConfig {
+                exclude: Some("aeiouAEIOU!@".to_string()),
+                avoid_ambiguous: Some(true),
+                ..Default::default()
+            },
+        );
+        for pass in passwords(&policy, 1000) {
+            assert!(
+                !pass
+                    .chars()
+                    .any(|c| "aeiouAEIOU!@".contains(c) || AMBIGUOUS_CHARS.contains(c)),
+                "{}",
+                pass
+            );
+        }
+    }
+
+    #[test]
+    fn custom_alphabet_is_respected() {
+        let policy = new_policy(
+            20,
+            GeneratorConfig {
+                alphabet: Some("xyz-_ 7".to_string()),
+                min_symbols: Some(2),
+                min_digits: Some(1),
+                ..Default::default()
+            },
+        );
+        for pass in passwords(&policy, 1000) {
+            assert!(pass.chars().all(|c| "xyz-_ 7".contains(c)), "{}", pass);
+            assert!(pass.contains('7'), "{}", pass);
+            assert!(pass.chars().filter(|c| "-_ ".contains(*c)).count() >= 2);
+        }
+        // repeated characters are not more likely than the others.
+        let repeated = new_policy(
+            1,
+            GeneratorConfig {
+                alphabet: Some("aaab".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(repeated.alphabet, vec!['a', 'b']);
+    }
+
+    #[test]
+    fn too_many_required_characters_fail() {
+        let error = PasswordPolicy::new(
+            5,
+            &GeneratorConfig {
+                min_digits: Some(3),
+                min_symbols: Some(3),
+                ..Default::default()
+            },
+        );
+        assert!(error.is_err());
+        // the default of one per class is dropped instead of failing.
+        assert!(PasswordPolicy::new(2, &GeneratorConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn zero_length_fails() {
+        assert!(PasswordPolicy::new(0, &GeneratorConfig::default()).is_err());
+        assert!(PasswordPolicy::new(1, &GeneratorConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn non_ascii_letters_are_not_symbols() {
+        let policy = new_policy(
+            10,
+            GeneratorConfig {
+                alphabet: Some("éüßñ€!".to_string()),
+                min_symbols: Some(3),
+                ..Default::default()
+            },
+        );
+        for pass in passwords(&policy, 1000) {
+            assert!(pass.chars().filter(|c| *c == '!').count() >= 3, "{}", pass);
+        }
+
+        let no_symbols = GeneratorConfig {
+            alphabet: Some("éüßñ".to_string()),
+            min_symbols: Some(1),
+            ..Default::default()
+        };
+        assert!(PasswordPolicy::new(10, &no_symbols).is_err());
+    }
+
+    #[test]
+    fn empty_alphabets_fail() {
+        let no_classes = GeneratorConfig {
+            uppercase: Some(false),
+            lowercase: Some(false),
+            digits: Some(false),
+            symbols: Some(false),
+            ..Default::default()
+        };
+        assert!(PasswordPolicy::new(16, &no_classes).is_err());
+
+        let all_excluded = GeneratorConfig {
+            alphabet: Some("abc".to_string()),
+            exclude: Some("cba".to_string()),
+            ..Default::default()
+        };
+        assert!(PasswordPolicy::new(16, &all_excluded).is_err());
+
+        // a minimum of a class the alphabet does not have cannot be met.
+        let missing_class = GeneratorConfig {
+            alphabet: Some("abc".to_string()),
+            min_digits: Some(1),
+            ..Default::default()
+        };
+        assert!(PasswordPolicy::new(16, &missing_class).is_err());
+    }
+
+    #[test]
+    fn wordlist_has_distinct_words() {
+        let words: Vec<&str> = WORDLIST.lines().collect();
+        assert_eq!(words.len(), 6usize.pow(4));
+        let mut sorted = words.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), words.len());
+        assert!(
+            words
+                .iter()
+                .all(|w| !w.is_empty() && w.chars().all(|c| c.is_ascii_lowercase()))
+        );
+    }
+
+    #[test]
+    fn passphrases_follow_the_policy() {
+        let policy = PassphrasePolicy {
+            words: 5,
+            separator: " + ".to_string(),
+            capitalize: true,
+            add_digit: true,
+            add_symbol: true,
+        };
+        for _ in 0..500 {
+            let phrase = policy.generate().unwrap();
+            let words: Vec<&str> = phrase.expose().split(" + ").collect();
+            assert_eq!(words.len(), 5, "{}", phrase.expose());
+            assert!(
+                words
+                    .iter()
+                    .all(|w| w.starts_with(|c: char| c.is_ascii_uppercase()))
+            );
+            let extra: String = words
+                .iter()
+                .flat_map(|w| w.chars().filter(|c| !c.is_ascii_alphabetic()))
+                .collect();
+            assert_eq!(extra.chars().filter(|c| c.is_ascii_digit()).count(), 1);
+            assert_eq!(
+                extra.chars().filter(|c| SYMBOL_CHARS.contains(*c)).count(),
+                1
+            );
+        }
+        let expected = 5.0 * 1296f64.log2() + 10f64.log2() + 10f64.log2();
+        assert!((policy.entropy() - expected).abs() < 1e-9);
+    }
 }
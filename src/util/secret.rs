@@ -0,0 +1,133 @@
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::{Zeroize, Zeroizing};
+
+use std::fmt;
+use std::io::{ErrorKind, Read};
+
+/// The size of the reads of read_from. Stdin buffers 8 KiB, and reads at least as large bypass that
+/// buffer, so the secret is not left in it.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// A password, key or any other value that must not outlive its use. The memory holding it is
+/// overwritten when it is dropped and Debug never shows it, so it cannot end up in a log or an
+/// error message by accident. Printing it, copying it or writing it out as JSON goes through
+/// expose() so that every use of the value is deliberate.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// The secret itself. The returned str must not be copied into a String that outlives it.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reads a secret until the end of `reader`. The buffer is grown by hand so that every smaller
+    /// one is wiped before it is freed, which reading into a String would not do.
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut buffer = Zeroizing::new(Vec::with_capacity(READ_CHUNK));
+        let mut chunk = Zeroizing::new(vec![0u8; READ_CHUNK]);
+        loop {
+            let n = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if buffer.len() + n > buffer.capacity() {
+                let mut larger = Zeroizing::new(Vec::with_capacity(2 * (buffer.len() + n)));
+                larger.extend_from_slice(&buffer);
+                buffer = larger;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+
+        match String::from_utf8(std::mem::take(&mut *buffer)) {
+            Ok(value) => Ok(Self(value)),
+            Err(e) => {
+                e.into_bytes().zeroize();
+                Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "The secret is not valid UTF-8",
+                ))
+            }
+        }
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+/// Secrets are written out as plain strings since JSON output and exports need the values.
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+impl ToSql for SecretString {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Text(self.0.as_bytes())))
+    }
+}
+
+impl FromSql for SecretString {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        String::column_result(value).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_from_reads_everything() {
+        for len in [0, 1, READ_CHUNK - 1, READ_CHUNK, 3 * READ_CHUNK + 7] {
+            let text: String = "pässwörd🔐".chars().cycle().take(len).collect();
+            let secret = SecretString::read_from(&mut text.as_bytes()).unwrap();
+            assert_eq!(secret.expose(), text);
+        }
+    }
+
+    #[test]
+    fn read_from_rejects_invalid_utf8() {
+        let bytes: &[u8] = &[b'a', 0xff, b'b'];
+        assert!(SecretString::read_from(&mut &bytes[..]).is_err());
+    }
+}
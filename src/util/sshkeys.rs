@@ -0,0 +1,90 @@
+use crate::util::argparser::KeyAlgorithm;
+use crate::util::errors::CMError;
+use crate::util::secret::SecretString;
+
+use ssh_key::rand_core::OsRng;
+use ssh_key::{Algorithm, LineEnding, PrivateKey};
+
+pub struct KeyPair {
+    /// The private key in OpenSSH format, encrypted if a passphrase was given.
+    pub private_key: SecretString,
+    /// The key type and base64 key data without a comment.
+    pub public_key: String,
+    pub comment: String,
+}
+
+pub fn generate_keypair(
+    algorithm: KeyAlgorithm,
+    comment: &str,
+    passphrase: &str,
+) -> Result<KeyPair, CMError> {
+    let algorithm = match algorithm {
+        KeyAlgorithm::Ed25519 => Algorithm::Ed25519,
+    };
+    let mut key = PrivateKey::random(&mut OsRng, algorithm)?;
+    key.set_comment(comment);
+
+    keypair_from_key(key, passphrase)
+}
+
+/// Parses a private key in OpenSSH format. If the key is encrypted the passphrase is checked by
+/// decrypting it.
+pub fn read_keypair(pem: &str, passphrase: &str) -> Result<KeyPair, CMError> {
+    let key = PrivateKey::from_openssh(pem)?;
+    let public_key = public_key_without_comment(&key)?;
+
+    // the comment of an encrypted key is only readable once it is decrypted.
+    let comment = if key.is_encrypted() {
+        key.decrypt(passphrase)?.comment().to_string()
+    } else {
+        key.comment().to_string()
+    };
+
+    Ok(KeyPair {
+        private_key: pem.into(),
+        public_key,
+        comment,
+    })
+}
+
+pub fn is_encrypted(pem: &str) -> Result<bool, CMError> {
+    let key = PrivateKey::from_openssh(pem)?;
+    Ok(key.is_encrypted())
+}
+
+/// Re-encrypts a private key with a new passphrase. An empty passphrase leaves the key unencrypted.
+pub fn change_passphrase(
+    pem: &str,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<KeyPair, CMError> {
+    let mut key = PrivateKey::from_openssh(pem)?;
+    if key.is_encrypted() {
+        key = key.decrypt(old_passphrase)?;
+    }
+
+    keypair_from_key(key, new_passphrase)
+}
+
+fn keypair_from_key(key: PrivateKey, passphrase: &str) -> Result<KeyPair, CMError> {
+    let public_key = public_key_without_comment(&key)?;
+    let comment = key.comment().to_string();
+
+    let key = if passphrase.is_empty() {
+        key
+    } else {
+        key.encrypt(&mut OsRng, passphrase)?
+    };
+
+    Ok(KeyPair {
+        private_key: key.to_openssh(LineEnding::LF)?.as_str().into(),
+        public_key,
+        comment,
+    })
+}
+
+fn public_key_without_comment(key: &PrivateKey) -> Result<String, CMError> {
+    let mut public_key = key.public_key().clone();
+    public_key.set_comment("");
+    Ok(public_key.to_openssh()?)
+}